@@ -12,7 +12,7 @@ use winit::{
     window::{Window, WindowId},
 };
 
-use renderer::Renderer;
+use renderer::{PresentMode, Renderer};
 
 use crate::{
     input,
@@ -20,11 +20,17 @@ use crate::{
 };
 
 mod conf {
+    use winit::keyboard::KeyCode;
+
     pub const WINDOW_TITLE: &str = "RAYGE Engine";
     pub const WINDOW_SIZE: (u32, u32) = (640, 480);
 
     pub const UPDATE_FREQUENCY: u64 = 100;
     pub const TARGET_FPS: u64 = 60;
+
+    // one of `PresentMode`'s names, case insensitive
+    pub const PRESENT_MODE_ENV_VAR: &str = "RAYGE_PRESENT_MODE";
+    pub const CYCLE_PRESENT_MODE_KEY: KeyCode = KeyCode::KeyP;
 }
 
 pub struct App {
@@ -40,7 +46,7 @@ pub struct App {
 struct Graphics {
     renderer: Renderer,
     stepper: TimeStepper<{ time_stepper::frequency_to_micros(conf::TARGET_FPS) }, { u64::MAX }>,
-    _window: Window,
+    window: Window,
 }
 
 impl App {
@@ -77,12 +83,36 @@ impl App {
 }
 
 impl Graphics {
-    fn new(renderer: Renderer, window: Window) -> Self {
-        Self {
+    fn new(mut renderer: Renderer, window: Window) -> renderer::Result<Self> {
+        if let Some(present_mode) = present_mode_preference() {
+            if renderer.present_modes().contains(&present_mode) {
+                renderer.set_present_mode(present_mode)?;
+            } else {
+                tracing::warn!("Present mode {present_mode:?} is unsupported by the surface");
+            }
+        }
+
+        Ok(Self {
             renderer,
             stepper: TimeStepper::default(),
-            _window: window,
-        }
+            window,
+        })
+    }
+
+    // Moves on to the next present mode the surface supports
+    fn cycle_present_mode(&mut self) -> renderer::Result<()> {
+        let present_modes = self.renderer.present_modes();
+        let Some(current) = self.renderer.present_mode() else {
+            return Ok(());
+        };
+        let next = present_modes
+            .iter()
+            .position(|&present_mode| present_mode == current)
+            .map_or(0, |idx| (idx + 1) % present_modes.len());
+        let next = present_modes[next];
+
+        tracing::info!("Switching to present mode {next:?}");
+        self.renderer.set_present_mode(next)
     }
 
     fn update(&mut self, delta_time: Duration) -> renderer::Result<()> {
@@ -109,14 +139,29 @@ impl ApplicationHandler for App {
                     event_loop.exit();
                     return;
                 }
-                Ok(window) => match Renderer::new(&window) {
-                    Err(e) => {
-                        self.error = Some(e.into());
-                        event_loop.exit();
-                        return;
+                Ok(window) => {
+                    let size = window.inner_size();
+                    match Renderer::new(&window, size.width, size.height) {
+                        Err(e) => {
+                            self.error = Some(e.into());
+                            event_loop.exit();
+                            return;
+                        }
+                        Ok(renderer) => {
+                            let device = renderer.device_properties();
+                            tracing::info!("Rendering with {device}");
+                            window.set_title(&format!("{} - {}", conf::WINDOW_TITLE, device.name));
+                            match Graphics::new(renderer, window) {
+                                Err(e) => {
+                                    self.error = Some(e.into());
+                                    event_loop.exit();
+                                    return;
+                                }
+                                Ok(graphics) => graphics,
+                            }
+                        }
                     }
-                    Ok(renderer) => Graphics::new(renderer, window),
-                },
+                }
             });
         }
     }
@@ -137,9 +182,15 @@ impl ApplicationHandler for App {
         event: WindowEvent,
     ) {
         match event {
-            WindowEvent::Resized(_) | WindowEvent::ScaleFactorChanged { .. } => {
+            WindowEvent::Resized(size) => {
                 if let Some(graphics) = &mut self.graphics {
-                    graphics.renderer.needs_resizing();
+                    graphics.renderer.needs_resizing(size.width, size.height);
+                }
+            }
+            WindowEvent::ScaleFactorChanged { .. } => {
+                if let Some(graphics) = &mut self.graphics {
+                    let size = graphics.window.inner_size();
+                    graphics.renderer.needs_resizing(size.width, size.height);
                 }
             }
             WindowEvent::CloseRequested
@@ -152,6 +203,23 @@ impl ApplicationHandler for App {
                     },
                 ..
             } => event_loop.exit(),
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(conf::CYCLE_PRESENT_MODE_KEY),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                if let Some(graphics) = &mut self.graphics
+                    && let Err(e) = graphics.cycle_present_mode()
+                {
+                    self.error = Some(e.into());
+                    event_loop.exit();
+                }
+            }
             WindowEvent::KeyboardInput {
                 event:
                     KeyEvent {
@@ -171,3 +239,18 @@ impl ApplicationHandler for App {
         }
     }
 }
+
+fn present_mode_preference() -> Option<PresentMode> {
+    let name = std::env::var(conf::PRESENT_MODE_ENV_VAR).ok()?;
+    let present_mode = PresentMode::ALL
+        .into_iter()
+        .find(|present_mode| format!("{present_mode:?}").eq_ignore_ascii_case(&name));
+    if present_mode.is_none() {
+        tracing::warn!(
+            "Unknown present mode {name:?} in {}, expected one of {:?}",
+            conf::PRESENT_MODE_ENV_VAR,
+            PresentMode::ALL
+        );
+    }
+    present_mode
+}
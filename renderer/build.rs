@@ -0,0 +1,37 @@
+use std::{env, path::PathBuf, process::Command};
+
+// Compiled to `$OUT_DIR/<name>.spv`, included by `src/shaders.rs`
+const SHADERS: &[&str] = &[
+    "path_trace.rgen",
+    "path_trace.rmiss",
+    "path_trace.rchit",
+    "fullscreen.vert",
+    "resolve.frag",
+];
+
+fn main() {
+    let shaders = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap()).join("shaders");
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    // from the Vulkan SDK unless overridden
+    let glslc = env::var("GLSLC").unwrap_or_else(|_| String::from("glslc"));
+
+    println!("cargo::rerun-if-changed={}", shaders.display());
+    println!("cargo::rerun-if-env-changed=GLSLC");
+
+    for shader in SHADERS {
+        let status = Command::new(&glslc)
+            .arg("--target-env=vulkan1.3")
+            .arg("-O")
+            .arg(shaders.join(shader))
+            .arg("-o")
+            .arg(out_dir.join(format!("{shader}.spv")))
+            .status()
+            .unwrap_or_else(|err| {
+                panic!(
+                    "Failed to run {glslc}, install the Vulkan SDK or set GLSLC to the path of \
+                     glslc, see README.md / {err}"
+                )
+            });
+        assert!(status.success(), "Failed to compile {shader}");
+    }
+}
@@ -0,0 +1,12 @@
+#extension GL_EXT_buffer_reference : require
+#extension GL_EXT_nonuniform_qualifier : require
+#extension GL_EXT_scalar_block_layout : require
+#extension GL_EXT_shader_explicit_arithmetic_types_int64 : require
+
+// bindless heap, see `base::bindless`
+layout(set = 0, binding = 1, rgba32f) uniform image2D storage_images[];
+layout(set = 0, binding = 2) uniform sampler samplers[];
+
+// see `bindless::conf::DEFAULT_SAMPLERS`
+const uint LINEAR_REPEAT_SAMPLER = 0;
+const uint NEAREST_CLAMP_SAMPLER = 1;
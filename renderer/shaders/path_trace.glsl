@@ -0,0 +1,7 @@
+struct Payload {
+    // negative on a miss
+    float distance;
+    vec3 normal;
+    vec3 albedo;
+    vec3 emission;
+};
@@ -0,0 +1,118 @@
+#version 460
+#extension GL_GOOGLE_include_directive : require
+
+#include "common.glsl"
+
+// see `resolve::ToneMapping`
+const uint CLAMP = 0;
+const uint REINHARD = 1;
+const uint ACES_FITTED = 2;
+const uint AGX = 3;
+
+// see `resolve::Encoding`
+const uint LINEAR = 0;
+const uint SRGB = 1;
+const uint PQ = 2;
+const uint SCRGB = 3;
+
+layout(push_constant, scalar) uniform PushConstants {
+    uint image;
+    uint tone_mapping;
+    uint encoding;
+    // in stops
+    float exposure;
+    // tone mapped 1.0 in output units, the display's peak for HDR encodings
+    float white_level;
+} pc;
+
+layout(location = 0) out vec4 color;
+
+// Stephen Hill's fit of the ACES RRT and ODT, sRGB in and out
+vec3 aces_fitted(vec3 x) {
+    const mat3 input_matrix = mat3(
+        0.59719, 0.07600, 0.02840,
+        0.35458, 0.90834, 0.13383,
+        0.04823, 0.01566, 0.83777
+    );
+    const mat3 output_matrix = mat3(
+        1.60475, -0.10208, -0.00327,
+        -0.53108, 1.10813, -0.07276,
+        -0.07367, -0.00605, 1.07602
+    );
+    x = input_matrix * x;
+    vec3 a = x * (x + 0.0245786) - 0.000090537;
+    vec3 b = x * (0.983729 * x + 0.4329510) + 0.238081;
+    return clamp(output_matrix * (a / b), 0.0, 1.0);
+}
+
+// Troy Sobotka's AgX base look, after Benjamin Wrensch's minimal fit
+vec3 agx(vec3 x) {
+    const mat3 inset = mat3(
+        0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+        0.0784335999999992, 0.878468636469772, 0.0784336,
+        0.0792237451477643, 0.0791661274605434, 0.879142973793104
+    );
+    const mat3 outset = mat3(
+        1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+        -0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+        -0.0990297440797205, -0.0989611768448433, 1.15107367264116
+    );
+    const float min_ev = -12.47393;
+    const float max_ev = 4.026069;
+
+    x = inset * x;
+    x = clamp(log2(max(x, 1e-10)), min_ev, max_ev);
+    x = (x - min_ev) / (max_ev - min_ev);
+
+    // sigmoid contrast curve
+    vec3 x2 = x * x;
+    vec3 x4 = x2 * x2;
+    x = 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+
+    // the curve targets a 2.2 display, so undo that to stay linear
+    return pow(max(outset * x, 0.0), vec3(2.2));
+}
+
+vec3 tone_map(vec3 x) {
+    switch (pc.tone_mapping) {
+        case REINHARD: return x / (1.0 + x);
+        case ACES_FITTED: return aces_fitted(x);
+        case AGX: return agx(x);
+        default: return clamp(x, 0.0, 1.0);
+    }
+}
+
+vec3 srgb_encode(vec3 x) {
+    return mix(12.92 * x, 1.055 * pow(x, vec3(1.0 / 2.4)) - 0.055, greaterThan(x, vec3(0.0031308)));
+}
+
+// SMPTE ST 2084, from linear with 1.0 at 10000 nits
+vec3 pq_encode(vec3 x) {
+    const float m1 = 0.1593017578125;
+    const float m2 = 78.84375;
+    const float c1 = 0.8359375;
+    const float c2 = 18.8515625;
+    const float c3 = 18.6875;
+    vec3 y = pow(max(x, 0.0), vec3(m1));
+    return pow((c1 + c2 * y) / (1.0 + c3 * y), vec3(m2));
+}
+
+const mat3 REC709_TO_REC2020 = mat3(
+    0.6274, 0.0691, 0.0164,
+    0.3293, 0.9195, 0.0880,
+    0.0433, 0.0114, 0.8956
+);
+
+vec3 encode(vec3 x) {
+    switch (pc.encoding) {
+        case SRGB: return srgb_encode(x);
+        case PQ: return pq_encode(REC709_TO_REC2020 * x * pc.white_level);
+        case SCRGB: return x * pc.white_level;
+        default: return x;
+    }
+}
+
+void main() {
+    vec3 hdr = imageLoad(storage_images[pc.image], ivec2(gl_FragCoord.xy)).rgb;
+    color = vec4(encode(tone_map(hdr * exp2(pc.exposure))), 1.0);
+}
@@ -0,0 +1,768 @@
+use ash::vk;
+
+use crate::{
+    base::{
+        buffer::{self, Buffer},
+        command::{self, Recorder},
+    },
+    context::{Context, device, queue},
+    destroy::{self, Destroy},
+};
+
+type Result<T> = core::result::Result<T, Error>;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Geometry {
+    // 32 bit indices, or a plain triangle list without them
+    Triangles {
+        vertices: vk::DeviceAddress,
+        vertex_format: vk::Format,
+        vertex_stride: vk::DeviceSize,
+        vertex_count: u32,
+        indices: Option<vk::DeviceAddress>,
+        triangle_count: u32,
+        opaque: bool,
+    },
+    // `vk::AabbPositionsKHR`, tightly packed for a `stride` of 0
+    Aabbs {
+        data: vk::DeviceAddress,
+        stride: vk::DeviceSize,
+        count: u32,
+        opaque: bool,
+    },
+}
+
+impl Geometry {
+    fn to_vk(self) -> (vk::AccelerationStructureGeometryKHR<'static>, u32) {
+        let (geometry_type, geometry, opaque, primitive_count) = match self {
+            Self::Triangles {
+                vertices,
+                vertex_format,
+                vertex_stride,
+                vertex_count,
+                indices,
+                triangle_count,
+                opaque,
+            } => {
+                let triangles = vk::AccelerationStructureGeometryTrianglesDataKHR::default()
+                    .vertex_format(vertex_format)
+                    .vertex_data(vk::DeviceOrHostAddressConstKHR {
+                        device_address: vertices,
+                    })
+                    .vertex_stride(vertex_stride)
+                    .max_vertex(vertex_count.saturating_sub(1))
+                    .index_type(if indices.is_some() {
+                        vk::IndexType::UINT32
+                    } else {
+                        vk::IndexType::NONE_KHR
+                    })
+                    .index_data(vk::DeviceOrHostAddressConstKHR {
+                        device_address: indices.unwrap_or(0),
+                    });
+                (
+                    vk::GeometryTypeKHR::TRIANGLES,
+                    vk::AccelerationStructureGeometryDataKHR { triangles },
+                    opaque,
+                    triangle_count,
+                )
+            }
+            Self::Aabbs {
+                data,
+                stride,
+                count,
+                opaque,
+            } => {
+                let aabbs = vk::AccelerationStructureGeometryAabbsDataKHR::default()
+                    .data(vk::DeviceOrHostAddressConstKHR {
+                        device_address: data,
+                    })
+                    .stride(if stride == 0 {
+                        size_of::<vk::AabbPositionsKHR>() as vk::DeviceSize
+                    } else {
+                        stride
+                    });
+                (
+                    vk::GeometryTypeKHR::AABBS,
+                    vk::AccelerationStructureGeometryDataKHR { aabbs },
+                    opaque,
+                    count,
+                )
+            }
+        };
+
+        let geometry = vk::AccelerationStructureGeometryKHR::default()
+            .geometry_type(geometry_type)
+            .geometry(geometry)
+            .flags(if opaque {
+                vk::GeometryFlagsKHR::OPAQUE
+            } else {
+                vk::GeometryFlagsKHR::empty()
+            });
+        (geometry, primitive_count)
+    }
+}
+
+pub struct BlasInfo<'a> {
+    pub geometries: &'a [Geometry],
+    pub name: &'a str,
+}
+
+impl BlasInfo<'_> {
+    fn to_vk(
+        &self,
+    ) -> (
+        Vec<vk::AccelerationStructureGeometryKHR<'static>>,
+        Vec<vk::AccelerationStructureBuildRangeInfoKHR>,
+    ) {
+        self.geometries
+            .iter()
+            .map(|geometry| {
+                let (geometry, primitive_count) = geometry.to_vk();
+                (
+                    geometry,
+                    vk::AccelerationStructureBuildRangeInfoKHR::default()
+                        .primitive_count(primitive_count),
+                )
+            })
+            .unzip()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Instance {
+    // row major 3x4
+    pub transform: [[f32; 4]; 3],
+    // `gl_InstanceCustomIndexEXT`, 24 bits
+    pub custom_index: u32,
+    pub mask: u8,
+    // 24 bits
+    pub sbt_offset: u32,
+    pub flags: vk::GeometryInstanceFlagsKHR,
+    pub blas: vk::DeviceAddress,
+}
+
+impl Instance {
+    pub const IDENTITY: [[f32; 4]; 3] = [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+    ];
+
+    pub const fn new(blas: &AccelerationStructure) -> Self {
+        Self {
+            transform: Self::IDENTITY,
+            custom_index: 0,
+            mask: 0xff,
+            sbt_offset: 0,
+            flags: vk::GeometryInstanceFlagsKHR::empty(),
+            blas: blas.address,
+        }
+    }
+
+    fn to_vk(self) -> vk::AccelerationStructureInstanceKHR {
+        let Self {
+            transform,
+            custom_index,
+            mask,
+            sbt_offset,
+            flags,
+            blas,
+        } = self;
+        vk::AccelerationStructureInstanceKHR {
+            transform: vk::TransformMatrixKHR {
+                matrix: transform.as_flattened().try_into().unwrap(),
+            },
+            instance_custom_index_and_mask: vk::Packed24_8::new(custom_index, mask),
+            instance_shader_binding_table_record_offset_and_flags: vk::Packed24_8::new(
+                sbt_offset,
+                u8::try_from(flags.as_raw()).expect("Instance flags are 8 bits wide"),
+            ),
+            acceleration_structure_reference: vk::AccelerationStructureReferenceKHR {
+                device_handle: blas,
+            },
+        }
+    }
+}
+
+pub struct AccelerationStructure {
+    buffer: Buffer,
+    address: vk::DeviceAddress,
+    handle: vk::AccelerationStructureKHR,
+}
+
+// Rebuilt or refitted every frame from instances written by the host. Each frame in flight gets
+// its own instance buffer, while the scratch buffer is shared and guarded by barriers.
+pub struct Tlas {
+    structure: AccelerationStructure,
+    instances: Vec<Buffer>,
+    scratch: Buffer,
+    capacity: u32,
+    // instance count of the last build, a refit has to keep it
+    built: Option<u32>,
+}
+
+impl AccelerationStructure {
+    fn new(
+        ctx: &Context,
+        ty: vk::AccelerationStructureTypeKHR,
+        size: vk::DeviceSize,
+        name: &str,
+    ) -> Result<Self> {
+        let mut buffer = Buffer::create(ctx, buffer::Kind::AccelerationStructure, size, name)?;
+
+        let create_info = vk::AccelerationStructureCreateInfoKHR::default()
+            .buffer(*buffer)
+            .size(size)
+            .ty(ty);
+        let handle = unsafe {
+            ctx.ext
+                .acceleration_structure
+                .create_acceleration_structure(&create_info, None)
+        };
+        let handle = match handle {
+            Ok(handle) => handle,
+            Err(err) => {
+                buffer.destroy_with(ctx);
+                return Err(Error::Create(err));
+            }
+        };
+
+        let address = unsafe {
+            ctx.ext
+                .acceleration_structure
+                .get_acceleration_structure_device_address(
+                    &vk::AccelerationStructureDeviceAddressInfoKHR::default()
+                        .acceleration_structure(handle),
+                )
+        };
+
+        let mut structure = Self {
+            buffer,
+            address,
+            handle,
+        };
+        if let Err(err) = ctx.set_debug_name(handle, name) {
+            structure.destroy_with(ctx);
+            return Err(err.into());
+        }
+        Ok(structure)
+    }
+
+    pub const fn address(&self) -> vk::DeviceAddress {
+        self.address
+    }
+}
+
+// Builds every BLAS in a single submission with one scratch buffer split between them, blocking
+// until done. With `compact` they are then copied into buffers of their compacted size.
+pub fn build_blases(
+    ctx: &Context,
+    infos: &[BlasInfo],
+    compact: bool,
+) -> Result<Vec<AccelerationStructure>> {
+    if infos.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let flags = if compact {
+        vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE
+            | vk::BuildAccelerationStructureFlagsKHR::ALLOW_COMPACTION
+    } else {
+        vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE
+    };
+
+    let (geometries, ranges): (Vec<_>, Vec<_>) = infos.iter().map(BlasInfo::to_vk).unzip();
+
+    let mut build_infos = geometries
+        .iter()
+        .map(|geometries| {
+            vk::AccelerationStructureBuildGeometryInfoKHR::default()
+                .ty(vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL)
+                .flags(flags)
+                .mode(vk::BuildAccelerationStructureModeKHR::BUILD)
+                .geometries(geometries)
+        })
+        .collect::<Vec<_>>();
+
+    let sizes = build_infos
+        .iter()
+        .zip(&ranges)
+        .map(|(build_info, ranges)| {
+            let primitive_counts = ranges
+                .iter()
+                .map(|range| range.primitive_count)
+                .collect::<Vec<_>>();
+            build_sizes(ctx, build_info, &primitive_counts)
+        })
+        .collect::<Vec<_>>();
+
+    let (scratch_offsets, scratch_size) = scratch_layout(
+        &sizes,
+        ctx.properties()
+            .acceleration_structure
+            .min_scratch_offset_alignment
+            .into(),
+    );
+
+    let mut structures = Vec::with_capacity(infos.len());
+    let mut scratch = None;
+
+    let result = (|| {
+        for (info, sizes) in infos.iter().zip(&sizes) {
+            structures.push(AccelerationStructure::new(
+                ctx,
+                vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL,
+                sizes.acceleration_structure_size,
+                info.name,
+            )?);
+        }
+        let scratch = scratch.insert(Buffer::create(
+            ctx,
+            buffer::Kind::Scratch,
+            scratch_size,
+            "blas:scratch",
+        )?);
+        let scratch_address = scratch.address().ok_or(Error::NoAddress)?;
+
+        for ((build_info, structure), offset) in build_infos
+            .iter_mut()
+            .zip(&structures)
+            .zip(&scratch_offsets)
+        {
+            *build_info = build_info
+                .dst_acceleration_structure(structure.handle)
+                .scratch_data(vk::DeviceOrHostAddressKHR {
+                    device_address: scratch_address + offset,
+                });
+        }
+        let ranges = ranges.iter().map(Vec::as_slice).collect::<Vec<_>>();
+        command::submit_once(
+            ctx,
+            queue::Kind::Graphics,
+            "blas:build",
+            |recorder| unsafe {
+                ctx.ext
+                    .acceleration_structure
+                    .cmd_build_acceleration_structures(**recorder, &build_infos, &ranges);
+            },
+        )?;
+
+        if compact {
+            compact_all(ctx, &mut structures, infos)?;
+        }
+        Ok(())
+    })();
+
+    if let Some(mut scratch) = scratch {
+        scratch.destroy_with(ctx);
+    }
+    match result {
+        Ok(()) => Ok(structures),
+        Err(err) => {
+            structures.destroy_with(ctx);
+            Err(err)
+        }
+    }
+}
+
+// Replaces every structure with a compacted copy, expects them to be built with `ALLOW_COMPACTION`
+fn compact_all(
+    ctx: &Context,
+    structures: &mut [AccelerationStructure],
+    infos: &[BlasInfo],
+) -> Result<()> {
+    let handles = structures.iter().map(|s| s.handle).collect::<Vec<_>>();
+    let query_count = u32::try_from(handles.len()).expect("Too many acceleration structures");
+    let query_pool = {
+        let create_info = vk::QueryPoolCreateInfo::default()
+            .query_type(vk::QueryType::ACCELERATION_STRUCTURE_COMPACTED_SIZE_KHR)
+            .query_count(query_count);
+        unsafe {
+            ctx.create_query_pool(&create_info, None)
+                .map_err(Error::CreateQueryPool)?
+        }
+    };
+
+    let mut compacted_sizes = vec![0u64; structures.len()];
+    let queried = command::submit_once(
+        ctx,
+        queue::Kind::Graphics,
+        "blas:compacted_sizes",
+        |recorder| unsafe {
+            recorder.memory_barrier(build_to_read());
+            ctx.cmd_reset_query_pool(**recorder, query_pool, 0, query_count);
+            ctx.ext
+                .acceleration_structure
+                .cmd_write_acceleration_structures_properties(
+                    **recorder,
+                    &handles,
+                    vk::QueryType::ACCELERATION_STRUCTURE_COMPACTED_SIZE_KHR,
+                    query_pool,
+                    0,
+                );
+        },
+    )
+    .map_err(Error::from)
+    .and_then(|()| unsafe {
+        ctx.get_query_pool_results(
+            query_pool,
+            0,
+            &mut compacted_sizes,
+            vk::QueryResultFlags::TYPE_64 | vk::QueryResultFlags::WAIT,
+        )
+        .map_err(Error::QueryResults)
+    });
+    unsafe {
+        ctx.destroy_query_pool(query_pool, None);
+    }
+    queried?;
+
+    let mut compacted = Vec::with_capacity(structures.len());
+    let result = (|| {
+        for (info, size) in infos.iter().zip(compacted_sizes) {
+            compacted.push(AccelerationStructure::new(
+                ctx,
+                vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL,
+                size,
+                info.name,
+            )?);
+        }
+
+        command::submit_once(ctx, queue::Kind::Graphics, "blas:compact", |recorder| {
+            for (src, dst) in structures.iter().zip(&compacted) {
+                let copy_info = vk::CopyAccelerationStructureInfoKHR::default()
+                    .src(src.handle)
+                    .dst(dst.handle)
+                    .mode(vk::CopyAccelerationStructureModeKHR::COMPACT);
+                unsafe {
+                    ctx.ext
+                        .acceleration_structure
+                        .cmd_copy_acceleration_structure(**recorder, &copy_info);
+                }
+            }
+        })?;
+        Ok(())
+    })();
+
+    match result {
+        Ok(()) => {
+            for (structure, compacted) in structures.iter_mut().zip(compacted) {
+                std::mem::replace(structure, compacted).destroy_with(ctx);
+            }
+            Ok(())
+        }
+        Err(err) => {
+            compacted.destroy_with(ctx);
+            Err(err)
+        }
+    }
+}
+
+impl Tlas {
+    pub fn new(ctx: &Context, capacity: u32, frames: usize, name: &str) -> Result<Self> {
+        let geometries = [instances_geometry(0)];
+        let build_info = vk::AccelerationStructureBuildGeometryInfoKHR::default()
+            .ty(vk::AccelerationStructureTypeKHR::TOP_LEVEL)
+            .flags(TLAS_FLAGS)
+            .geometries(&geometries);
+        let sizes = build_sizes(ctx, &build_info, &[capacity]);
+
+        let mut structure = AccelerationStructure::new(
+            ctx,
+            vk::AccelerationStructureTypeKHR::TOP_LEVEL,
+            sizes.acceleration_structure_size,
+            name,
+        )?;
+        let mut instances = Vec::with_capacity(frames);
+        let result = (|| {
+            for frame in 0..frames {
+                instances.push(Buffer::create(
+                    ctx,
+                    buffer::Kind::AccelerationStructureInstances,
+                    size_of::<vk::AccelerationStructureInstanceKHR>() as vk::DeviceSize
+                        * vk::DeviceSize::from(capacity.max(1)),
+                    &format!("{name}:instances#{frame}"),
+                )?);
+            }
+            Buffer::create(
+                ctx,
+                buffer::Kind::Scratch,
+                sizes.build_scratch_size.max(sizes.update_scratch_size),
+                &format!("{name}:scratch"),
+            )
+        })();
+
+        match result {
+            Ok(scratch) => Ok(Self {
+                structure,
+                instances,
+                scratch,
+                capacity,
+                built: None,
+            }),
+            Err(err) => {
+                instances.destroy_with(ctx);
+                structure.destroy_with(ctx);
+                Err(err.into())
+            }
+        }
+    }
+
+    // Uploads the instances for frame slot `frame` and records a build, or a refit when `refit`
+    // and the instance count is unchanged since the last build. Refits are cheaper but trace
+    // slower the further instances move, so callers should rebuild once in a while.
+    pub fn record(
+        &mut self,
+        recorder: &Recorder,
+        frame: usize,
+        instances: &[Instance],
+        refit: bool,
+    ) -> Result<()> {
+        let ctx = recorder.ctx();
+        let count = u32::try_from(instances.len())
+            .ok()
+            .filter(|&count| count <= self.capacity)
+            .ok_or(Error::TooManyInstances {
+                count: instances.len(),
+                capacity: self.capacity,
+            })?;
+
+        let instance_buffer = &self.instances[frame];
+        let data = instances
+            .iter()
+            .map(|instance| instance.to_vk())
+            .collect::<Vec<_>>();
+        instance_buffer.write(ctx, 0, &data)?;
+
+        let update = refit && self.built == Some(count);
+        let geometries = [instances_geometry(
+            instance_buffer.address().ok_or(Error::NoAddress)?,
+        )];
+        let build_info = vk::AccelerationStructureBuildGeometryInfoKHR::default()
+            .ty(vk::AccelerationStructureTypeKHR::TOP_LEVEL)
+            .flags(TLAS_FLAGS)
+            .mode(if update {
+                vk::BuildAccelerationStructureModeKHR::UPDATE
+            } else {
+                vk::BuildAccelerationStructureModeKHR::BUILD
+            })
+            .src_acceleration_structure(if update {
+                self.structure.handle
+            } else {
+                vk::AccelerationStructureKHR::null()
+            })
+            .dst_acceleration_structure(self.structure.handle)
+            .geometries(&geometries)
+            .scratch_data(vk::DeviceOrHostAddressKHR {
+                device_address: self.scratch.address().ok_or(Error::NoAddress)?,
+            });
+        let ranges = [vk::AccelerationStructureBuildRangeInfoKHR::default().primitive_count(count)];
+
+        // the previous frame's build may still be using the scratch buffer and its traces the
+        // structure itself, while instanced BLASes come from earlier builds or compactions
+        recorder.memory_barrier(
+            vk::MemoryBarrier2::default()
+                .src_stage_mask(
+                    vk::PipelineStageFlags2::ACCELERATION_STRUCTURE_BUILD_KHR
+                        | vk::PipelineStageFlags2::ACCELERATION_STRUCTURE_COPY_KHR
+                        | vk::PipelineStageFlags2::RAY_TRACING_SHADER_KHR,
+                )
+                .src_access_mask(vk::AccessFlags2::ACCELERATION_STRUCTURE_WRITE_KHR)
+                .dst_stage_mask(vk::PipelineStageFlags2::ACCELERATION_STRUCTURE_BUILD_KHR)
+                .dst_access_mask(
+                    vk::AccessFlags2::ACCELERATION_STRUCTURE_READ_KHR
+                        | vk::AccessFlags2::ACCELERATION_STRUCTURE_WRITE_KHR,
+                ),
+        );
+        unsafe {
+            ctx.ext
+                .acceleration_structure
+                .cmd_build_acceleration_structures(**recorder, &[build_info], &[&ranges]);
+        }
+        recorder.memory_barrier(build_to_read());
+
+        self.built = Some(count);
+        Ok(())
+    }
+
+    pub const fn address(&self) -> vk::DeviceAddress {
+        self.structure.address
+    }
+}
+
+const TLAS_FLAGS: vk::BuildAccelerationStructureFlagsKHR =
+    vk::BuildAccelerationStructureFlagsKHR::from_raw(
+        vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE.as_raw()
+            | vk::BuildAccelerationStructureFlagsKHR::ALLOW_UPDATE.as_raw(),
+    );
+
+// Makes built structures readable by later builds, copies and traces
+fn build_to_read() -> vk::MemoryBarrier2<'static> {
+    vk::MemoryBarrier2::default()
+        .src_stage_mask(vk::PipelineStageFlags2::ACCELERATION_STRUCTURE_BUILD_KHR)
+        .src_access_mask(vk::AccessFlags2::ACCELERATION_STRUCTURE_WRITE_KHR)
+        .dst_stage_mask(
+            vk::PipelineStageFlags2::ACCELERATION_STRUCTURE_BUILD_KHR
+                | vk::PipelineStageFlags2::ACCELERATION_STRUCTURE_COPY_KHR
+                | vk::PipelineStageFlags2::RAY_TRACING_SHADER_KHR,
+        )
+        .dst_access_mask(vk::AccessFlags2::ACCELERATION_STRUCTURE_READ_KHR)
+}
+
+fn instances_geometry(data: vk::DeviceAddress) -> vk::AccelerationStructureGeometryKHR<'static> {
+    vk::AccelerationStructureGeometryKHR::default()
+        .geometry_type(vk::GeometryTypeKHR::INSTANCES)
+        .geometry(vk::AccelerationStructureGeometryDataKHR {
+            instances: vk::AccelerationStructureGeometryInstancesDataKHR::default()
+                .array_of_pointers(false)
+                .data(vk::DeviceOrHostAddressConstKHR {
+                    device_address: data,
+                }),
+        })
+}
+
+// Offsets of each build's region in a shared scratch buffer, and the size of that buffer
+fn scratch_layout(
+    sizes: &[vk::AccelerationStructureBuildSizesInfoKHR],
+    scratch_alignment: vk::DeviceSize,
+) -> (Vec<vk::DeviceSize>, vk::DeviceSize) {
+    let scratch_offsets = sizes
+        .iter()
+        .scan(0, |offset, sizes| {
+            let start = *offset;
+            *offset = (start + sizes.build_scratch_size).next_multiple_of(scratch_alignment);
+            Some(start)
+        })
+        .collect::<Vec<_>>();
+    let scratch_size = scratch_offsets.last().copied().unwrap_or(0)
+        + sizes.last().map_or(0, |sizes| sizes.build_scratch_size);
+    (scratch_offsets, scratch_size)
+}
+
+fn build_sizes(
+    ctx: &Context,
+    build_info: &vk::AccelerationStructureBuildGeometryInfoKHR,
+    max_primitive_counts: &[u32],
+) -> vk::AccelerationStructureBuildSizesInfoKHR<'static> {
+    let mut sizes = vk::AccelerationStructureBuildSizesInfoKHR::default();
+    unsafe {
+        ctx.ext
+            .acceleration_structure
+            .get_acceleration_structure_build_sizes(
+                vk::AccelerationStructureBuildTypeKHR::DEVICE,
+                build_info,
+                max_primitive_counts,
+                &mut sizes,
+            );
+    }
+    sizes
+}
+
+impl std::ops::Deref for AccelerationStructure {
+    type Target = vk::AccelerationStructureKHR;
+    fn deref(&self) -> &Self::Target {
+        &self.handle
+    }
+}
+
+impl std::ops::Deref for Tlas {
+    type Target = vk::AccelerationStructureKHR;
+    fn deref(&self) -> &Self::Target {
+        &self.structure.handle
+    }
+}
+
+impl Destroy<Context> for AccelerationStructure {
+    fn destroy_with(&mut self, ctx: &Context) {
+        let Self {
+            buffer,
+            address: _,
+            handle,
+        } = self;
+        ctx.untrack(*handle);
+        unsafe {
+            ctx.ext
+                .acceleration_structure
+                .destroy_acceleration_structure(*handle, None);
+        }
+        *handle = vk::AccelerationStructureKHR::null();
+        buffer.destroy_with(ctx);
+    }
+}
+
+impl Drop for AccelerationStructure {
+    fn drop(&mut self) {
+        destroy::debug_assert_destroyed(self.handle, "acceleration structure");
+    }
+}
+
+impl Destroy<Context> for Tlas {
+    fn destroy_with(&mut self, ctx: &Context) {
+        let Self {
+            structure,
+            instances,
+            scratch,
+            capacity: _,
+            built,
+        } = self;
+        structure.destroy_with(ctx);
+        instances.destroy_with(ctx);
+        scratch.destroy_with(ctx);
+        *built = None;
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("failed to create acceleration structure / {0}")]
+    Create(vk::Result),
+    #[error("failed to create query pool / {0}")]
+    CreateQueryPool(vk::Result),
+    #[error("failed to get compacted sizes / {0}")]
+    QueryResults(vk::Result),
+    #[error("buffer has no device address")]
+    NoAddress,
+    #[error("{count} instances exceed the capacity of {capacity}")]
+    TooManyInstances { count: usize, capacity: u32 },
+    #[error("buffer / {0}")]
+    Buffer(#[from] buffer::Error),
+    #[error("command / {0}")]
+    Command(#[from] command::Error),
+    #[error("device / {0}")]
+    Device(#[from] device::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sizes(
+        build_scratch_sizes: &[vk::DeviceSize],
+    ) -> Vec<vk::AccelerationStructureBuildSizesInfoKHR<'static>> {
+        build_scratch_sizes
+            .iter()
+            .map(|&build_scratch_size| {
+                vk::AccelerationStructureBuildSizesInfoKHR::default()
+                    .build_scratch_size(build_scratch_size)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn aligns_each_region_start() {
+        assert_eq!(
+            scratch_layout(&sizes(&[100, 256, 1]), 128),
+            (vec![0, 128, 384], 385)
+        );
+    }
+
+    #[test]
+    fn leaves_the_last_region_unpadded() {
+        assert_eq!(scratch_layout(&sizes(&[100]), 128), (vec![0], 100));
+    }
+
+    #[test]
+    fn is_empty_without_builds() {
+        assert_eq!(scratch_layout(&[], 128), (Vec::new(), 0));
+    }
+}
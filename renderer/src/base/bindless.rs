@@ -0,0 +1,536 @@
+use std::{collections::HashMap, marker::PhantomData};
+
+use ash::vk;
+
+use crate::{
+    base::{command::Recorder, sampler},
+    context::{Context, device, properties::DescriptorIndexingProperties},
+    destroy::{self, Destroy},
+};
+
+type Result<T> = core::result::Result<T, Error>;
+
+mod conf {
+    use ash::vk;
+
+    use crate::base::sampler;
+
+    // upper bounds, lowered to fit the device limits
+    pub const SAMPLED_IMAGES: u32 = 16 * 1024;
+    pub const STORAGE_IMAGES: u32 = 1024;
+    pub const SAMPLERS: u32 = 256;
+    pub const STORAGE_BUFFERS: u32 = 16 * 1024;
+    // the minimum every implementation supports
+    pub const PUSH_CONSTANTS_SIZE: u32 = 128;
+    // registered on creation in this order, matching the constants in `common.glsl`
+    pub const DEFAULT_SAMPLERS: [sampler::Info; 2] = [
+        sampler::Info::new(),
+        sampler::Info::new()
+            .nearest()
+            .address_mode(vk::SamplerAddressMode::CLAMP_TO_EDGE),
+    ];
+}
+
+pub trait Kind: Copy {
+    const BINDING: u32;
+    const TYPE: vk::DescriptorType;
+    const NAME: &str;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SampledImage;
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct StorageImage;
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Sampler;
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct StorageBuffer;
+
+impl Kind for SampledImage {
+    const BINDING: u32 = 0;
+    const TYPE: vk::DescriptorType = vk::DescriptorType::SAMPLED_IMAGE;
+    const NAME: &str = "sampled image";
+}
+
+impl Kind for StorageImage {
+    const BINDING: u32 = 1;
+    const TYPE: vk::DescriptorType = vk::DescriptorType::STORAGE_IMAGE;
+    const NAME: &str = "storage image";
+}
+
+impl Kind for Sampler {
+    const BINDING: u32 = 2;
+    const TYPE: vk::DescriptorType = vk::DescriptorType::SAMPLER;
+    const NAME: &str = "sampler";
+}
+
+impl Kind for StorageBuffer {
+    const BINDING: u32 = 3;
+    const TYPE: vk::DescriptorType = vk::DescriptorType::STORAGE_BUFFER;
+    const NAME: &str = "storage buffer";
+}
+
+const BINDINGS: [vk::DescriptorType; 4] = [
+    SampledImage::TYPE,
+    StorageImage::TYPE,
+    Sampler::TYPE,
+    StorageBuffer::TYPE,
+];
+
+// Index into the array of kind `K`, as seen by shaders
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Id<K: Kind> {
+    index: u32,
+    kind: PhantomData<K>,
+}
+
+impl<K: Kind> Id<K> {
+    pub const fn index(self) -> u32 {
+        self.index
+    }
+}
+
+// A single descriptor set with one array per resource kind, bound once for every pipeline.
+// Writes are queued and only applied by `flush`, and freed slots are only handed out again once
+// the frames that may still read them are done.
+pub struct Heap {
+    slots: [Slots; BINDINGS.len()],
+    pending: Vec<Write>,
+    samplers: sampler::Cache,
+    // slot of every sampler in `samplers`, which are never removed
+    sampler_ids: HashMap<vk::Sampler, Id<Sampler>>,
+    set: vk::DescriptorSet,
+    pipeline_layout: vk::PipelineLayout,
+    set_layout: vk::DescriptorSetLayout,
+    pool: vk::DescriptorPool,
+}
+
+#[derive(Default)]
+struct Slots {
+    capacity: u32,
+    next: u32,
+    free: Vec<u32>,
+    // freed slots along with the timeline value of their last use
+    retired: Vec<(u64, u32)>,
+}
+
+struct Write {
+    binding: u32,
+    index: u32,
+    info: Info,
+}
+
+enum Info {
+    Image(vk::DescriptorImageInfo),
+    Buffer(vk::DescriptorBufferInfo),
+}
+
+impl Heap {
+    pub fn new(ctx: &Context) -> Result<Self> {
+        let capacities = Self::capacities(&ctx.properties().descriptor_indexing);
+        tracing::debug!("Bindless capacities {capacities:?}");
+
+        let pool = {
+            let pool_sizes = BINDINGS
+                .into_iter()
+                .zip(capacities)
+                .map(|(ty, count)| {
+                    vk::DescriptorPoolSize::default()
+                        .ty(ty)
+                        .descriptor_count(count)
+                })
+                .collect::<Vec<_>>();
+            let create_info = vk::DescriptorPoolCreateInfo::default()
+                .flags(vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND)
+                .max_sets(1)
+                .pool_sizes(&pool_sizes);
+
+            unsafe {
+                ctx.create_descriptor_pool(&create_info, None)
+                    .map_err(Error::CreatePool)?
+            }
+        };
+
+        let mut heap = Self {
+            slots: capacities.map(|capacity| Slots {
+                capacity,
+                ..Slots::default()
+            }),
+            pending: Vec::new(),
+            samplers: sampler::Cache::default(),
+            sampler_ids: HashMap::new(),
+            set: vk::DescriptorSet::null(),
+            pipeline_layout: vk::PipelineLayout::null(),
+            set_layout: vk::DescriptorSetLayout::null(),
+            pool,
+        };
+        match heap.create_set(ctx, capacities) {
+            Ok(()) => Ok(heap),
+            Err(err) => {
+                heap.destroy_with(ctx);
+                Err(err)
+            }
+        }
+    }
+
+    // Creates the layouts and the set out of the pool, `destroy_with` cleans up when this fails midway
+    fn create_set(&mut self, ctx: &Context, capacities: [u32; BINDINGS.len()]) -> Result<()> {
+        ctx.set_debug_name(self.pool, "bindless")?;
+
+        self.set_layout = {
+            let bindings = (0..)
+                .zip(BINDINGS.into_iter().zip(capacities))
+                .map(|(binding, (ty, count))| {
+                    vk::DescriptorSetLayoutBinding::default()
+                        .binding(binding)
+                        .descriptor_type(ty)
+                        .descriptor_count(count)
+                        .stage_flags(vk::ShaderStageFlags::ALL)
+                })
+                .collect::<Vec<_>>();
+            let binding_flags = [vk::DescriptorBindingFlags::PARTIALLY_BOUND
+                | vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
+                | vk::DescriptorBindingFlags::UPDATE_UNUSED_WHILE_PENDING;
+                BINDINGS.len()];
+            let mut binding_flags_info = vk::DescriptorSetLayoutBindingFlagsCreateInfo::default()
+                .binding_flags(&binding_flags);
+            let create_info = vk::DescriptorSetLayoutCreateInfo::default()
+                .flags(vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL)
+                .bindings(&bindings)
+                .push_next(&mut binding_flags_info);
+
+            unsafe {
+                ctx.create_descriptor_set_layout(&create_info, None)
+                    .map_err(Error::CreateSetLayout)?
+            }
+        };
+        ctx.set_debug_name(self.set_layout, "bindless")?;
+
+        self.pipeline_layout = {
+            let set_layouts = [self.set_layout];
+            let push_constant_ranges = [vk::PushConstantRange::default()
+                .stage_flags(vk::ShaderStageFlags::ALL)
+                .size(conf::PUSH_CONSTANTS_SIZE)];
+            let create_info = vk::PipelineLayoutCreateInfo::default()
+                .set_layouts(&set_layouts)
+                .push_constant_ranges(&push_constant_ranges);
+
+            unsafe {
+                ctx.create_pipeline_layout(&create_info, None)
+                    .map_err(Error::CreatePipelineLayout)?
+            }
+        };
+        ctx.set_debug_name(self.pipeline_layout, "bindless")?;
+
+        self.set = {
+            let set_layouts = [self.set_layout];
+            let allocate_info = vk::DescriptorSetAllocateInfo::default()
+                .descriptor_pool(self.pool)
+                .set_layouts(&set_layouts);
+
+            unsafe {
+                ctx.allocate_descriptor_sets(&allocate_info)
+                    .map_err(Error::Allocate)?[0]
+            }
+        };
+        ctx.set_debug_name(self.set, "bindless")?;
+
+        for info in conf::DEFAULT_SAMPLERS {
+            self.add_sampler(ctx, info)?;
+        }
+        Ok(())
+    }
+
+    // `conf` capacities, shrunk to the per set and per stage limits, then scaled down together if
+    // they exceed the per stage resource limit, which samplers don't count towards
+    fn capacities(limits: &DescriptorIndexingProperties) -> [u32; BINDINGS.len()] {
+        let mut capacities = [
+            conf::SAMPLED_IMAGES.min(limits.sampled_images),
+            conf::STORAGE_IMAGES.min(limits.storage_images),
+            conf::SAMPLERS.min(limits.samplers),
+            conf::STORAGE_BUFFERS.min(limits.storage_buffers),
+        ];
+
+        let resources = [
+            SampledImage::BINDING,
+            StorageImage::BINDING,
+            StorageBuffer::BINDING,
+        ];
+        let total = resources
+            .iter()
+            .map(|&binding| u64::from(capacities[binding as usize]))
+            .sum::<u64>();
+        if total > u64::from(limits.resources) {
+            for binding in resources {
+                let capacity = &mut capacities[binding as usize];
+                *capacity = (u64::from(*capacity) * u64::from(limits.resources) / total) as u32;
+            }
+        }
+        capacities
+    }
+
+    // Shared by every pipeline so the heap only has to be bound once per command buffer
+    pub const fn pipeline_layout(&self) -> vk::PipelineLayout {
+        self.pipeline_layout
+    }
+
+    pub fn add_sampled_image(
+        &mut self,
+        view: vk::ImageView,
+        layout: vk::ImageLayout,
+    ) -> Result<Id<SampledImage>> {
+        self.add(Info::Image(
+            vk::DescriptorImageInfo::default()
+                .image_view(view)
+                .image_layout(layout),
+        ))
+    }
+
+    pub fn add_storage_image(&mut self, view: vk::ImageView) -> Result<Id<StorageImage>> {
+        self.add(Info::Image(
+            vk::DescriptorImageInfo::default()
+                .image_view(view)
+                .image_layout(vk::ImageLayout::GENERAL),
+        ))
+    }
+
+    // Identical `info`s share one sampler and slot, kept until the heap is destroyed
+    pub fn add_sampler(&mut self, ctx: &Context, info: sampler::Info) -> Result<Id<Sampler>> {
+        let sampler = self.samplers.get(ctx, info)?;
+        if let Some(&id) = self.sampler_ids.get(&sampler) {
+            return Ok(id);
+        }
+
+        let id = self.add(Info::Image(
+            vk::DescriptorImageInfo::default().sampler(sampler),
+        ))?;
+        self.sampler_ids.insert(sampler, id);
+        Ok(id)
+    }
+
+    pub fn add_storage_buffer(
+        &mut self,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        range: vk::DeviceSize,
+    ) -> Result<Id<StorageBuffer>> {
+        self.add(Info::Buffer(
+            vk::DescriptorBufferInfo::default()
+                .buffer(buffer)
+                .offset(offset)
+                .range(range),
+        ))
+    }
+
+    // The slot keeps pointing at the old resource until it is reused, so `last_use` has to cover
+    // every submission that may still index it
+    pub fn remove<K: Kind>(&mut self, id: Id<K>, last_use: u64) {
+        let slots = &mut self.slots[K::BINDING as usize];
+        self.pending
+            .retain(|w| w.binding != K::BINDING || w.index != id.index);
+        slots.retired.push((last_use, id.index));
+    }
+
+    // Applies the queued writes and recycles the slots whose last use is at most `completed`.
+    // Must happen before recording any command buffer that indexes the new slots.
+    pub fn flush(&mut self, ctx: &Context, completed: u64) {
+        for slots in &mut self.slots {
+            let Slots { free, retired, .. } = slots;
+            retired.retain(|&(last_use, index)| {
+                let done = last_use <= completed;
+                if done {
+                    free.push(index);
+                }
+                !done
+            });
+        }
+
+        if self.pending.is_empty() {
+            return;
+        }
+
+        let writes = self
+            .pending
+            .iter()
+            .map(|w| {
+                let write = vk::WriteDescriptorSet::default()
+                    .dst_set(self.set)
+                    .dst_binding(w.binding)
+                    .dst_array_element(w.index)
+                    .descriptor_type(BINDINGS[w.binding as usize]);
+                match &w.info {
+                    Info::Image(info) => write.image_info(std::slice::from_ref(info)),
+                    Info::Buffer(info) => write.buffer_info(std::slice::from_ref(info)),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        unsafe {
+            ctx.update_descriptor_sets(&writes, &[]);
+        }
+        self.pending.clear();
+    }
+
+    pub fn bind(&self, recorder: &Recorder, bind_point: vk::PipelineBindPoint) {
+        unsafe {
+            recorder.ctx().cmd_bind_descriptor_sets(
+                **recorder,
+                bind_point,
+                self.pipeline_layout,
+                0,
+                &[self.set],
+                &[],
+            );
+        }
+    }
+
+    fn add<K: Kind>(&mut self, info: Info) -> Result<Id<K>> {
+        let slots = &mut self.slots[K::BINDING as usize];
+        let index = match slots.free.pop() {
+            Some(index) => index,
+            None if slots.next < slots.capacity => {
+                slots.next += 1;
+                slots.next - 1
+            }
+            None => {
+                return Err(Error::Full {
+                    kind: K::NAME,
+                    capacity: slots.capacity,
+                });
+            }
+        };
+
+        self.pending.push(Write {
+            binding: K::BINDING,
+            index,
+            info,
+        });
+        Ok(Id {
+            index,
+            kind: PhantomData,
+        })
+    }
+}
+
+impl Destroy<Context> for Heap {
+    fn destroy_with(&mut self, ctx: &Context) {
+        let Self {
+            slots: _,
+            pending,
+            samplers,
+            sampler_ids,
+            set,
+            pipeline_layout,
+            set_layout,
+            pool,
+        } = self;
+        pending.clear();
+        sampler_ids.clear();
+        samplers.destroy_with(ctx);
+        ctx.untrack(*set);
+        ctx.untrack(*pipeline_layout);
+        ctx.untrack(*set_layout);
+        ctx.untrack(*pool);
+        unsafe {
+            ctx.destroy_pipeline_layout(*pipeline_layout, None);
+            ctx.destroy_descriptor_set_layout(*set_layout, None);
+            // also frees the set
+            ctx.destroy_descriptor_pool(*pool, None);
+        }
+        *set = vk::DescriptorSet::null();
+        *pipeline_layout = vk::PipelineLayout::null();
+        *set_layout = vk::DescriptorSetLayout::null();
+        *pool = vk::DescriptorPool::null();
+    }
+}
+
+impl Drop for Heap {
+    fn drop(&mut self) {
+        destroy::debug_assert_destroyed(self.pool, "bindless heap");
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("failed to create descriptor pool / {0}")]
+    CreatePool(vk::Result),
+    #[error("failed to create descriptor set layout / {0}")]
+    CreateSetLayout(vk::Result),
+    #[error("failed to create pipeline layout / {0}")]
+    CreatePipelineLayout(vk::Result),
+    #[error("failed to allocate descriptor set / {0}")]
+    Allocate(vk::Result),
+    #[error("all {capacity} {kind} slots are in use")]
+    Full { kind: &'static str, capacity: u32 },
+    #[error("sampler / {0}")]
+    Sampler(#[from] sampler::Error),
+    #[error("device / {0}")]
+    Device(#[from] device::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNLIMITED: DescriptorIndexingProperties = DescriptorIndexingProperties {
+        sampled_images: u32::MAX,
+        storage_images: u32::MAX,
+        samplers: u32::MAX,
+        storage_buffers: u32::MAX,
+        resources: u32::MAX,
+    };
+
+    #[test]
+    fn keeps_conf_capacities_within_limits() {
+        assert_eq!(
+            Heap::capacities(&UNLIMITED),
+            [
+                conf::SAMPLED_IMAGES,
+                conf::STORAGE_IMAGES,
+                conf::SAMPLERS,
+                conf::STORAGE_BUFFERS
+            ]
+        );
+    }
+
+    #[test]
+    fn clamps_to_per_type_limits() {
+        let limits = DescriptorIndexingProperties {
+            sampled_images: 500,
+            storage_images: 100,
+            samplers: 16,
+            storage_buffers: 1000,
+            ..UNLIMITED
+        };
+        assert_eq!(Heap::capacities(&limits), [500, 100, 16, 1000]);
+    }
+
+    #[test]
+    fn scales_resources_down_together_leaving_samplers() {
+        let total = conf::SAMPLED_IMAGES + conf::STORAGE_IMAGES + conf::STORAGE_BUFFERS;
+        let limits = DescriptorIndexingProperties {
+            resources: total / 2,
+            ..UNLIMITED
+        };
+        let capacities = Heap::capacities(&limits);
+        assert_eq!(
+            capacities,
+            [
+                conf::SAMPLED_IMAGES / 2,
+                conf::STORAGE_IMAGES / 2,
+                conf::SAMPLERS,
+                conf::STORAGE_BUFFERS / 2
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_resources_exactly_at_the_limit() {
+        let total = conf::SAMPLED_IMAGES + conf::STORAGE_IMAGES + conf::STORAGE_BUFFERS;
+        let limits = DescriptorIndexingProperties {
+            resources: total,
+            ..UNLIMITED
+        };
+        assert_eq!(Heap::capacities(&limits), Heap::capacities(&UNLIMITED));
+    }
+}
@@ -0,0 +1,335 @@
+use ash::vk;
+use vk_mem::Alloc;
+
+use crate::{
+    base::command,
+    context::{Context, device, queue},
+    destroy::{self, Destroy},
+};
+
+type Result<T> = core::result::Result<T, Error>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Vertex,
+    Index,
+    Uniform,
+    Storage,
+    Staging,
+    AccelerationStructure,
+    // instances and other read-only acceleration structure build data
+    AccelerationStructureInput,
+    // top level instances, rewritten from the host every frame
+    AccelerationStructureInstances,
+    Scratch,
+    ShaderBindingTable,
+}
+
+impl Kind {
+    fn usage(self) -> vk::BufferUsageFlags {
+        let usage = match self {
+            Self::Vertex => {
+                vk::BufferUsageFlags::VERTEX_BUFFER
+                    | vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
+                    | vk::BufferUsageFlags::TRANSFER_DST
+            }
+            Self::Index => {
+                vk::BufferUsageFlags::INDEX_BUFFER
+                    | vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
+                    | vk::BufferUsageFlags::TRANSFER_DST
+            }
+            Self::Uniform => vk::BufferUsageFlags::UNIFORM_BUFFER,
+            Self::Storage => {
+                vk::BufferUsageFlags::STORAGE_BUFFER
+                    | vk::BufferUsageFlags::TRANSFER_SRC
+                    | vk::BufferUsageFlags::TRANSFER_DST
+            }
+            // host to device uploads or device to host read backs
+            Self::Staging => {
+                return vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST;
+            }
+            Self::AccelerationStructure => vk::BufferUsageFlags::ACCELERATION_STRUCTURE_STORAGE_KHR,
+            Self::AccelerationStructureInput => {
+                vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
+                    | vk::BufferUsageFlags::TRANSFER_DST
+            }
+            Self::AccelerationStructureInstances => {
+                vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
+            }
+            Self::Scratch => vk::BufferUsageFlags::STORAGE_BUFFER,
+            Self::ShaderBindingTable => {
+                vk::BufferUsageFlags::SHADER_BINDING_TABLE_KHR | vk::BufferUsageFlags::TRANSFER_DST
+            }
+        };
+        usage | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
+    }
+
+    fn allocation_flags(self) -> vk_mem::AllocationCreateFlags {
+        match self {
+            Self::Staging => {
+                vk_mem::AllocationCreateFlags::MAPPED
+                    | vk_mem::AllocationCreateFlags::HOST_ACCESS_RANDOM
+            }
+            Self::Uniform | Self::AccelerationStructureInstances => {
+                vk_mem::AllocationCreateFlags::MAPPED
+                    | vk_mem::AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE
+            }
+            _ => vk_mem::AllocationCreateFlags::empty(),
+        }
+    }
+
+    fn alignment(self, ctx: &Context) -> vk::DeviceSize {
+        let properties = ctx.properties();
+        match self {
+            Self::Scratch => properties
+                .acceleration_structure
+                .min_scratch_offset_alignment
+                .into(),
+            Self::ShaderBindingTable => properties
+                .ray_tracing_pipeline
+                .shader_group
+                .base_alignment
+                .into(),
+            // required of the instance data address
+            Self::AccelerationStructureInstances => 16,
+            _ => 1,
+        }
+    }
+}
+
+pub struct Buffer {
+    allocation: vk_mem::Allocation,
+    handle: vk::Buffer,
+    size: vk::DeviceSize,
+    address: Option<vk::DeviceAddress>,
+}
+
+impl Buffer {
+    pub fn create(ctx: &Context, kind: Kind, size: vk::DeviceSize, name: &str) -> Result<Self> {
+        if size == 0 {
+            return Err(Error::Empty);
+        }
+
+        let usage = kind.usage();
+        let (handle, allocation) = {
+            let create_info = vk::BufferCreateInfo::default()
+                .size(size)
+                .usage(usage)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+            let allocation_info = vk_mem::AllocationCreateInfo {
+                flags: kind.allocation_flags(),
+                usage: vk_mem::MemoryUsage::Auto,
+                ..Default::default()
+            };
+
+            unsafe {
+                ctx.allocator()
+                    .create_buffer_with_alignment(
+                        &create_info,
+                        &allocation_info,
+                        kind.alignment(ctx),
+                    )
+                    .map_err(Error::Create)?
+            }
+        };
+        let address = usage
+            .contains(vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS)
+            .then(|| unsafe {
+                ctx.get_buffer_device_address(
+                    &vk::BufferDeviceAddressInfo::default().buffer(handle),
+                )
+            });
+
+        let mut buffer = Self {
+            allocation,
+            handle,
+            size,
+            address,
+        };
+        if let Err(err) = ctx.set_debug_name(handle, name) {
+            buffer.destroy_with(ctx);
+            return Err(err.into());
+        }
+        Ok(buffer)
+    }
+
+    // Creates a device local buffer filled with `data` through a temporary staging buffer,
+    // copied on the transfer queue and handed over to the graphics queue. Blocks until both are
+    // done.
+    pub fn upload<T: Copy>(ctx: &Context, kind: Kind, data: &[T], name: &str) -> Result<Self> {
+        let size = size_of_val(data) as vk::DeviceSize;
+
+        let mut staging = Self::create(ctx, Kind::Staging, size, &format!("{name}:staging"))?;
+        let result = staging.write(ctx, 0, data).and_then(|()| {
+            let mut buffer = Self::create(ctx, kind, size, name)?;
+            match buffer.copy_for_graphics(ctx, &staging, &format!("{name}:upload")) {
+                Ok(()) => Ok(buffer),
+                Err(err) => {
+                    buffer.destroy_with(ctx);
+                    Err(err)
+                }
+            }
+        });
+        staging.destroy_with(ctx);
+
+        result
+    }
+
+    // Copies all of `source` on the transfer queue, then, if the graphics queue is of another
+    // family, acquires the buffer there. Both submissions are waited on from the host, which
+    // orders the acquire after the release.
+    fn copy_for_graphics(&self, ctx: &Context, source: &Self, name: &str) -> Result<()> {
+        let queues = ctx.queues();
+        let (transfer, graphics) = (queues.transfer().family(), queues.graphics().family());
+        let ownership = (transfer != graphics).then(|| {
+            vk::BufferMemoryBarrier2::default()
+                .src_queue_family_index(transfer)
+                .dst_queue_family_index(graphics)
+                .buffer(self.handle)
+                .size(vk::WHOLE_SIZE)
+        });
+
+        command::submit_once(ctx, queue::Kind::Transfer, name, |recorder| {
+            unsafe {
+                ctx.cmd_copy_buffer(
+                    **recorder,
+                    source.handle,
+                    self.handle,
+                    &[vk::BufferCopy::default().size(source.size)],
+                );
+            }
+            if let Some(release) = ownership {
+                recorder.buffer_barrier(&[release
+                    .src_stage_mask(vk::PipelineStageFlags2::COPY)
+                    .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)]);
+            }
+        })?;
+
+        if let Some(acquire) = ownership {
+            command::submit_once(
+                ctx,
+                queue::Kind::Graphics,
+                &format!("{name}:acquire"),
+                |recorder| {
+                    recorder.buffer_barrier(&[acquire
+                        .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+                        .dst_access_mask(vk::AccessFlags2::MEMORY_READ)]);
+                },
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn write<T: Copy>(&self, ctx: &Context, offset: vk::DeviceSize, data: &[T]) -> Result<()> {
+        let size = size_of_val(data) as vk::DeviceSize;
+        if offset + size > self.size {
+            return Err(Error::OutOfBounds {
+                offset,
+                size,
+                capacity: self.size,
+            });
+        }
+
+        let mapped_data = ctx
+            .allocator()
+            .get_allocation_info(&self.allocation)
+            .mapped_data;
+        if mapped_data.is_null() {
+            return Err(Error::NotHostWritable);
+        }
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                data.as_ptr().cast::<u8>(),
+                mapped_data.cast::<u8>().add(offset as usize),
+                size as usize,
+            );
+            ctx.allocator()
+                .flush_allocation(&self.allocation, offset, size)
+                .map_err(Error::Flush)
+        }
+    }
+
+    // Expects device writes to have been made visible to the host
+    pub fn read(&self, ctx: &Context) -> Result<Vec<u8>> {
+        let mapped_data = ctx
+            .allocator()
+            .get_allocation_info(&self.allocation)
+            .mapped_data;
+        if mapped_data.is_null() {
+            return Err(Error::NotHostReadable);
+        }
+
+        unsafe {
+            ctx.allocator()
+                .invalidate_allocation(&self.allocation, 0, vk::WHOLE_SIZE)
+                .map_err(Error::Invalidate)?;
+            Ok(std::slice::from_raw_parts(mapped_data.cast::<u8>(), self.size as usize).to_vec())
+        }
+    }
+
+    pub const fn size(&self) -> vk::DeviceSize {
+        self.size
+    }
+
+    // `None` for staging buffers, which are never accessed from shaders
+    pub const fn address(&self) -> Option<vk::DeviceAddress> {
+        self.address
+    }
+}
+
+impl std::ops::Deref for Buffer {
+    type Target = vk::Buffer;
+    fn deref(&self) -> &Self::Target {
+        &self.handle
+    }
+}
+
+impl Destroy<Context> for Buffer {
+    fn destroy_with(&mut self, ctx: &Context) {
+        let Self {
+            allocation,
+            handle,
+            size: _,
+            address: _,
+        } = self;
+        ctx.untrack(*handle);
+        unsafe {
+            ctx.allocator().destroy_buffer(*handle, allocation);
+        }
+        *handle = vk::Buffer::null();
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        destroy::debug_assert_destroyed(self.handle, "buffer");
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("buffer size must be greater than 0")]
+    Empty,
+    #[error("failed to create buffer / {0}")]
+    Create(vk::Result),
+    #[error("buffer memory is not host writable")]
+    NotHostWritable,
+    #[error("buffer memory is not host readable")]
+    NotHostReadable,
+    #[error("write of {size} bytes at offset {offset} exceeds buffer size {capacity}")]
+    OutOfBounds {
+        offset: vk::DeviceSize,
+        size: vk::DeviceSize,
+        capacity: vk::DeviceSize,
+    },
+    #[error("failed to flush buffer memory / {0}")]
+    Flush(vk::Result),
+    #[error("failed to invalidate buffer memory / {0}")]
+    Invalidate(vk::Result),
+    #[error("command / {0}")]
+    Command(#[from] command::Error),
+    #[error("device / {0}")]
+    Device(#[from] device::Error),
+}
@@ -0,0 +1,349 @@
+use ash::vk;
+
+use crate::{
+    base::fence,
+    context::{
+        Context, device,
+        queue::{self, Queue},
+    },
+    destroy::{self, Destroy},
+};
+
+type Result<T> = core::result::Result<T, Error>;
+
+// Command buffers of a single queue family, all recycled at once by `reset`
+pub struct Pool {
+    name: String,
+    family: u32,
+    buffers: Vec<vk::CommandBuffer>,
+    used: usize,
+    handle: vk::CommandPool,
+}
+
+// One pool per queue kind, meant to be owned by a frame and reset once its fence is signalled
+pub struct Pools {
+    graphics: Pool,
+    transfer: Pool,
+}
+
+pub struct Recorder<'a> {
+    ctx: &'a Context,
+    family: u32,
+    handle: vk::CommandBuffer,
+}
+
+pub struct Recorded {
+    handle: vk::CommandBuffer,
+}
+
+impl Pool {
+    pub fn new(ctx: &Context, queue_family: u32, name: &str) -> Result<Self> {
+        let handle = {
+            let create_info = vk::CommandPoolCreateInfo::default()
+                .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+                .queue_family_index(queue_family);
+
+            unsafe {
+                ctx.create_command_pool(&create_info, None)
+                    .map_err(Error::CreatePool)?
+            }
+        };
+        let mut pool = Self {
+            name: name.to_owned(),
+            family: queue_family,
+            buffers: Vec::new(),
+            used: 0,
+            handle,
+        };
+        if let Err(err) = ctx.set_debug_name(handle, &format!("{name}_command_pool")) {
+            pool.destroy_with(ctx);
+            return Err(err.into());
+        }
+        Ok(pool)
+    }
+
+    // Expects none of the buffers handed out since the last reset to be pending execution
+    pub fn reset(&mut self, ctx: &Context) -> Result<()> {
+        unsafe {
+            ctx.reset_command_pool(self.handle, vk::CommandPoolResetFlags::empty())
+                .map_err(Error::Reset)?;
+        }
+        self.used = 0;
+        Ok(())
+    }
+
+    pub fn begin<'a>(&mut self, ctx: &'a Context) -> Result<Recorder<'a>> {
+        if self.used == self.buffers.len() {
+            let allocate_info = vk::CommandBufferAllocateInfo::default()
+                .command_pool(self.handle)
+                .level(vk::CommandBufferLevel::PRIMARY)
+                .command_buffer_count(1);
+
+            let handle = unsafe {
+                ctx.allocate_command_buffers(&allocate_info)
+                    .map_err(Error::Allocate)?[0]
+            };
+            // freed along with the pool, so tracked before naming can fail
+            self.buffers.push(handle);
+            ctx.set_debug_name(handle, &format!("{}#{}", self.name, self.used))?;
+        }
+
+        let handle = self.buffers[self.used];
+        self.used += 1;
+
+        let begin_info = vk::CommandBufferBeginInfo::default()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+        unsafe {
+            ctx.begin_command_buffer(handle, &begin_info)
+                .map_err(Error::Begin)?;
+        }
+
+        Ok(Recorder {
+            ctx,
+            family: self.family,
+            handle,
+        })
+    }
+}
+
+impl Pools {
+    pub fn new(ctx: &Context, name: &str) -> Result<Self> {
+        let queues = ctx.queues();
+        let mut graphics = Pool::new(ctx, queues.graphics().family(), &format!("{name}:graphics"))?;
+        match Pool::new(ctx, queues.transfer().family(), &format!("{name}:transfer")) {
+            Ok(transfer) => Ok(Self { graphics, transfer }),
+            Err(err) => {
+                graphics.destroy_with(ctx);
+                Err(err)
+            }
+        }
+    }
+
+    pub fn reset(&mut self, ctx: &Context) -> Result<()> {
+        let Self { graphics, transfer } = self;
+
+        graphics.reset(ctx)?;
+        transfer.reset(ctx)
+    }
+
+    pub fn begin<'a>(&mut self, ctx: &'a Context, kind: queue::Kind) -> Result<Recorder<'a>> {
+        match kind {
+            queue::Kind::Graphics => self.graphics.begin(ctx),
+            queue::Kind::Transfer => self.transfer.begin(ctx),
+        }
+    }
+}
+
+impl<'a> Recorder<'a> {
+    pub const fn ctx(&self) -> &'a Context {
+        self.ctx
+    }
+
+    pub const fn family(&self) -> u32 {
+        self.family
+    }
+
+    pub fn pipeline_barrier(&self, image_barriers: &[vk::ImageMemoryBarrier2]) {
+        if image_barriers.is_empty() {
+            return;
+        }
+
+        let dependency_info = vk::DependencyInfo::default().image_memory_barriers(image_barriers);
+        unsafe {
+            self.ctx
+                .cmd_pipeline_barrier2(self.handle, &dependency_info);
+        }
+    }
+
+    pub fn buffer_barrier(&self, buffer_barriers: &[vk::BufferMemoryBarrier2]) {
+        let dependency_info = vk::DependencyInfo::default().buffer_memory_barriers(buffer_barriers);
+        unsafe {
+            self.ctx
+                .cmd_pipeline_barrier2(self.handle, &dependency_info);
+        }
+    }
+
+    pub fn memory_barrier(&self, barrier: vk::MemoryBarrier2) {
+        let memory_barriers = [barrier];
+        let dependency_info = vk::DependencyInfo::default().memory_barriers(&memory_barriers);
+        unsafe {
+            self.ctx
+                .cmd_pipeline_barrier2(self.handle, &dependency_info);
+        }
+    }
+
+    // Pushes `constants` for every stage at offset 0, expects `T` to have no padding bytes
+    pub fn push_constants<T: Copy>(&self, layout: vk::PipelineLayout, constants: &T) {
+        let bytes = unsafe {
+            std::slice::from_raw_parts(std::ptr::from_ref(constants).cast::<u8>(), size_of::<T>())
+        };
+        unsafe {
+            self.ctx
+                .cmd_push_constants(self.handle, layout, vk::ShaderStageFlags::ALL, 0, bytes);
+        }
+    }
+
+    #[cfg(feature = "debug-names")]
+    pub fn begin_label(&self, name: &str) {
+        let label_name = std::ffi::CString::new(name).unwrap();
+        let label = vk::DebugUtilsLabelEXT::default().label_name(&label_name);
+
+        unsafe {
+            self.ctx
+                .ext
+                .debug_utils
+                .cmd_begin_debug_utils_label(self.handle, &label);
+        }
+    }
+
+    #[cfg(not(feature = "debug-names"))]
+    pub fn begin_label(&self, _: &str) {}
+
+    #[cfg(feature = "debug-names")]
+    pub fn end_label(&self) {
+        unsafe {
+            self.ctx
+                .ext
+                .debug_utils
+                .cmd_end_debug_utils_label(self.handle);
+        }
+    }
+
+    #[cfg(not(feature = "debug-names"))]
+    pub fn end_label(&self) {}
+
+    pub fn finish(self) -> Result<Recorded> {
+        let Self {
+            ctx,
+            family: _,
+            handle,
+        } = self;
+        unsafe { ctx.end_command_buffer(handle).map_err(Error::End)? };
+        Ok(Recorded { handle })
+    }
+}
+
+impl std::ops::Deref for Recorder<'_> {
+    type Target = vk::CommandBuffer;
+    fn deref(&self) -> &Self::Target {
+        &self.handle
+    }
+}
+
+pub fn submit(
+    ctx: &Context,
+    queue: &Queue,
+    recorded: &[Recorded],
+    wait: &[vk::SemaphoreSubmitInfo],
+    signal: &[vk::SemaphoreSubmitInfo],
+    fence: vk::Fence,
+) -> Result<()> {
+    let command_buffer_infos = recorded
+        .iter()
+        .map(|r| vk::CommandBufferSubmitInfo::default().command_buffer(r.handle))
+        .collect::<Vec<_>>();
+
+    let submit_info = vk::SubmitInfo2::default()
+        .wait_semaphore_infos(wait)
+        .command_buffer_infos(&command_buffer_infos)
+        .signal_semaphore_infos(signal);
+
+    unsafe {
+        ctx.queue_submit2(**queue, &[submit_info], fence)
+            .map_err(Error::Submit)
+    }
+}
+
+// Records and submits to the given queue on a transient pool, then blocks until execution is done.
+// Meant for setup work such as uploads, not for anything per frame.
+pub fn submit_once(
+    ctx: &Context,
+    kind: queue::Kind,
+    name: &str,
+    record: impl FnOnce(&Recorder),
+) -> Result<()> {
+    let queue = ctx.queues().get(kind);
+    let mut pool = Pool::new(ctx, queue.family(), name)?;
+    let done = ctx.fences().take(ctx, &format!("{name}:done"));
+    let mut done = match done {
+        Ok(done) => done,
+        Err(err) => {
+            pool.destroy_with(ctx);
+            return Err(err.into());
+        }
+    };
+
+    let result = (|| {
+        let recorder = pool.begin(ctx)?;
+        recorder.begin_label(name);
+        record(&recorder);
+        recorder.end_label();
+
+        submit(ctx, queue, &[recorder.finish()?], &[], &[], *done)?;
+        Ok(done.wait(ctx)?)
+    })();
+
+    pool.destroy_with(ctx);
+    match result {
+        Ok(()) => Ok(ctx.fences().recycle(ctx, done)?),
+        Err(err) => {
+            done.destroy_with(ctx);
+            Err(err)
+        }
+    }
+}
+
+impl Destroy<Context> for Pool {
+    fn destroy_with(&mut self, ctx: &Context) {
+        let Self {
+            name: _,
+            family: _,
+            buffers,
+            used: _,
+            handle,
+        } = self;
+        for buffer in buffers.drain(..) {
+            ctx.untrack(buffer);
+        }
+        ctx.untrack(*handle);
+        unsafe {
+            ctx.destroy_command_pool(*handle, None);
+        }
+        *handle = vk::CommandPool::null();
+    }
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        destroy::debug_assert_destroyed(self.handle, "command pool");
+    }
+}
+
+impl Destroy<Context> for Pools {
+    fn destroy_with(&mut self, ctx: &Context) {
+        let Self { graphics, transfer } = self;
+        graphics.destroy_with(ctx);
+        transfer.destroy_with(ctx);
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("failed to create command pool / {0}")]
+    CreatePool(vk::Result),
+    #[error("failed to allocate command buffer / {0}")]
+    Allocate(vk::Result),
+    #[error("failed to reset command pool / {0}")]
+    Reset(vk::Result),
+    #[error("failed to begin command buffer / {0}")]
+    Begin(vk::Result),
+    #[error("failed to end command buffer / {0}")]
+    End(vk::Result),
+    #[error("failed to submit command buffers / {0}")]
+    Submit(vk::Result),
+    #[error("fence / {0}")]
+    Fence(#[from] fence::Error),
+    #[error("device / {0}")]
+    Device(#[from] device::Error),
+}
@@ -1,8 +1,10 @@
+use std::time::Duration;
+
 use ash::vk;
 
 use crate::{
     context::{Context, device},
-    destroy::Destroy,
+    destroy::{self, Destroy},
 };
 
 type Result<T> = core::result::Result<T, Error>;
@@ -11,6 +13,12 @@ pub struct Fence {
     handle: vk::Fence,
 }
 
+// Unsignalled fences kept around for reuse by one-shot submissions
+#[derive(Default)]
+pub struct Pool {
+    free: Vec<Fence>,
+}
+
 impl Fence {
     pub fn new(ctx: &Context, signaled: bool, name: &str) -> Result<Self> {
         let handle = {
@@ -25,18 +33,144 @@ impl Fence {
                     .map_err(Error::Create)?
             }
         };
-        ctx.set_debug_name(handle, name)?;
+        let mut fence = Self { handle };
+        if let Err(err) = ctx.set_debug_name(handle, name) {
+            fence.destroy_with(ctx);
+            return Err(err.into());
+        }
+        Ok(fence)
+    }
+
+    pub fn wait(&self, ctx: &Context) -> Result<()> {
+        Self::wait_all(ctx, &[self], None)
+    }
+
+    pub fn wait_timeout(&self, ctx: &Context, timeout: Duration) -> Result<()> {
+        Self::wait_all(ctx, &[self], Some(timeout))
+    }
+
+    pub fn wait_all(ctx: &Context, fences: &[&Self], timeout: Option<Duration>) -> Result<()> {
+        Self::wait_for(ctx, fences, true, timeout)
+    }
+
+    // Index of the first of `fences` found signalled, `None` only when there are none to wait on
+    pub fn wait_any(
+        ctx: &Context,
+        fences: &[&Self],
+        timeout: Option<Duration>,
+    ) -> Result<Option<usize>> {
+        Self::wait_for(ctx, fences, false, timeout)?;
+        for (idx, fence) in fences.iter().enumerate() {
+            if fence.is_signaled(ctx)? {
+                return Ok(Some(idx));
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn is_signaled(&self, ctx: &Context) -> Result<bool> {
+        unsafe {
+            ctx.get_fence_status(self.handle).map_err(|err| match err {
+                vk::Result::ERROR_DEVICE_LOST => Error::DeviceLost,
+                err => Error::Status(err),
+            })
+        }
+    }
+
+    pub fn reset(&self, ctx: &Context) -> Result<()> {
+        Self::reset_all(ctx, &[self])
+    }
+
+    pub fn reset_all(ctx: &Context, fences: &[&Self]) -> Result<()> {
+        if fences.is_empty() {
+            return Ok(());
+        }
+
+        let handles = fences.iter().map(|f| f.handle).collect::<Vec<_>>();
+        unsafe { ctx.reset_fences(&handles).map_err(Error::Reset) }
+    }
+
+    fn wait_for(
+        ctx: &Context,
+        fences: &[&Self],
+        wait_all: bool,
+        timeout: Option<Duration>,
+    ) -> Result<()> {
+        // Vulkan needs at least one fence, and waiting on none is done right away
+        if fences.is_empty() {
+            return Ok(());
+        }
+
+        let handles = fences.iter().map(|f| f.handle).collect::<Vec<_>>();
+        let timeout = timeout.map_or(u64::MAX, |t| {
+            u64::try_from(t.as_nanos()).unwrap_or(u64::MAX)
+        });
+
+        unsafe {
+            ctx.wait_for_fences(&handles, wait_all, timeout)
+                .map_err(|err| match err {
+                    vk::Result::TIMEOUT => Error::Timeout,
+                    vk::Result::ERROR_DEVICE_LOST => Error::DeviceLost,
+                    err => Error::Wait(err),
+                })
+        }
+    }
+}
+
+impl Pool {
+    pub fn take(&mut self, ctx: &Context, name: &str) -> Result<Fence> {
+        match self.free.pop() {
+            Some(mut fence) => {
+                if let Err(err) = ctx.set_debug_name(fence.handle, name) {
+                    fence.destroy_with(ctx);
+                    return Err(err.into());
+                }
+                Ok(fence)
+            }
+            None => Fence::new(ctx, false, name),
+        }
+    }
+
+    // Expects the fence to be signalled or never submitted
+    pub fn recycle(&mut self, ctx: &Context, mut fence: Fence) -> Result<()> {
+        if let Err(err) = fence.reset(ctx) {
+            fence.destroy_with(ctx);
+            return Err(err);
+        }
+        self.free.push(fence);
+        Ok(())
+    }
+}
 
-        Ok(Self { handle })
+impl std::ops::Deref for Fence {
+    type Target = vk::Fence;
+    fn deref(&self) -> &Self::Target {
+        &self.handle
     }
 }
 
 impl Destroy<Context> for Fence {
     fn destroy_with(&mut self, ctx: &Context) {
         let Self { handle } = self;
+        ctx.untrack(*handle);
         unsafe {
             ctx.destroy_fence(*handle, None);
         }
+        *handle = vk::Fence::null();
+    }
+}
+
+impl Drop for Fence {
+    fn drop(&mut self) {
+        destroy::debug_assert_destroyed(self.handle, "fence");
+    }
+}
+
+impl Destroy<Context> for Pool {
+    fn destroy_with(&mut self, ctx: &Context) {
+        let Self { free } = self;
+        free.destroy_with(ctx);
+        free.clear();
     }
 }
 
@@ -44,6 +178,16 @@ impl Destroy<Context> for Fence {
 pub enum Error {
     #[error("failed to create fence / {0}")]
     Create(vk::Result),
+    #[error("timed out waiting for fence")]
+    Timeout,
+    #[error("device lost")]
+    DeviceLost,
+    #[error("failed to wait for fence / {0}")]
+    Wait(vk::Result),
+    #[error("failed to query fence status / {0}")]
+    Status(vk::Result),
+    #[error("failed to reset fence / {0}")]
+    Reset(vk::Result),
     #[error("device / {0}")]
     Device(#[from] device::Error),
 }
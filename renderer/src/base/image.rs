@@ -1,90 +1,603 @@
 use std::marker::ConstParamTy;
 
 use ash::vk;
+use vk_mem::Alloc;
 
 use crate::{
-    context::{Context, device, surface},
-    destroy::Destroy,
+    base::{buffer::Buffer, command},
+    context::{Context, device},
+    destroy::{self, Destroy},
 };
 
 pub type Result<T> = core::result::Result<T, Error>;
 
 #[derive(ConstParamTy, Eq, PartialEq)]
 pub enum Format {
-    Hdr,
+    // negotiated with the surface at runtime
     Swapchain,
+    // color
+    R8Unorm,
+    Rg8Unorm,
+    Rgba8Unorm,
+    Rgba8Srgb,
+    Bgra8Unorm,
+    Bgra8Srgb,
+    R16Float,
+    Rg16Float,
+    Rgba16Float,
+    R32Uint,
+    R32Float,
+    Rg32Float,
+    Rgba32Float,
+    Rgb10A2Unorm,
+    Rg11B10Float,
+    // depth / stencil
+    D16Unorm,
+    D32Float,
+    S8Uint,
+    D24UnormS8Uint,
+    D32FloatS8Uint,
 }
 
-impl From<Format> for vk::Format {
-    fn from(format: Format) -> Self {
-        match format {
-            Format::Hdr => Self::R32G32B32A32_SFLOAT,
-            Format::Swapchain => surface::conf::FORMAT.format,
+impl Format {
+    // `None` for formats only known at runtime and combined depth / stencil ones, which have no
+    // single texel layout as each aspect is copied on its own
+    const fn texel_size(self) -> Option<usize> {
+        Some(match self {
+            Self::Swapchain | Self::D24UnormS8Uint | Self::D32FloatS8Uint => return None,
+            Self::R8Unorm | Self::S8Uint => 1,
+            Self::Rg8Unorm | Self::R16Float | Self::D16Unorm => 2,
+            Self::Rgba8Unorm
+            | Self::Rgba8Srgb
+            | Self::Bgra8Unorm
+            | Self::Bgra8Srgb
+            | Self::Rg16Float
+            | Self::R32Uint
+            | Self::R32Float
+            | Self::Rgb10A2Unorm
+            | Self::Rg11B10Float
+            | Self::D32Float => 4,
+            Self::Rgba16Float | Self::Rg32Float => 8,
+            Self::Rgba32Float => 16,
+        })
+    }
+
+    const fn aspect_flags(self) -> vk::ImageAspectFlags {
+        match self {
+            Self::D16Unorm | Self::D32Float => vk::ImageAspectFlags::DEPTH,
+            Self::S8Uint => vk::ImageAspectFlags::STENCIL,
+            Self::D24UnormS8Uint | Self::D32FloatS8Uint => vk::ImageAspectFlags::from_raw(
+                vk::ImageAspectFlags::DEPTH.as_raw() | vk::ImageAspectFlags::STENCIL.as_raw(),
+            ),
+            _ => vk::ImageAspectFlags::COLOR,
+        }
+    }
+
+    // Sampled and storage views may only have one of depth and stencil, depth is the useful one
+    const fn view_aspect_flags(self) -> vk::ImageAspectFlags {
+        match self {
+            Self::D24UnormS8Uint | Self::D32FloatS8Uint => vk::ImageAspectFlags::DEPTH,
+            _ => self.aspect_flags(),
+        }
+    }
+}
+
+// Fails for `Format::Swapchain`, negotiated with the surface at runtime and passed explicitly,
+// see `surface::Config::format`
+impl TryFrom<Format> for vk::Format {
+    type Error = Error;
+    fn try_from(format: Format) -> Result<Self> {
+        Ok(match format {
+            Format::Swapchain => return Err(Error::RuntimeFormat),
+            Format::R8Unorm => Self::R8_UNORM,
+            Format::Rg8Unorm => Self::R8G8_UNORM,
+            Format::Rgba8Unorm => Self::R8G8B8A8_UNORM,
+            Format::Rgba8Srgb => Self::R8G8B8A8_SRGB,
+            Format::Bgra8Unorm => Self::B8G8R8A8_UNORM,
+            Format::Bgra8Srgb => Self::B8G8R8A8_SRGB,
+            Format::R16Float => Self::R16_SFLOAT,
+            Format::Rg16Float => Self::R16G16_SFLOAT,
+            Format::Rgba16Float => Self::R16G16B16A16_SFLOAT,
+            Format::R32Uint => Self::R32_UINT,
+            Format::R32Float => Self::R32_SFLOAT,
+            Format::Rg32Float => Self::R32G32_SFLOAT,
+            Format::Rgba32Float => Self::R32G32B32A32_SFLOAT,
+            Format::Rgb10A2Unorm => Self::A2B10G10R10_UNORM_PACK32,
+            Format::Rg11B10Float => Self::B10G11R11_UFLOAT_PACK32,
+            Format::D16Unorm => Self::D16_UNORM,
+            Format::D32Float => Self::D32_SFLOAT,
+            Format::S8Uint => Self::S8_UINT,
+            Format::D24UnormS8Uint => Self::D24_UNORM_S8_UINT,
+            Format::D32FloatS8Uint => Self::D32_SFLOAT_S8_UINT,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Info {
+    pub extent: vk::Extent2D,
+    pub usage: vk::ImageUsageFlags,
+    pub mip_levels: u32,
+    // multiple of 6 for cube maps, one cube per 6 layers
+    pub array_layers: u32,
+    pub cube: bool,
+}
+
+impl Info {
+    pub const fn new(extent: vk::Extent2D, usage: vk::ImageUsageFlags) -> Self {
+        Self {
+            extent,
+            usage,
+            mip_levels: 1,
+            array_layers: 1,
+            cube: false,
+        }
+    }
+
+    pub const fn mip_levels(self, mip_levels: u32) -> Self {
+        Self { mip_levels, ..self }
+    }
+
+    // Down to 1x1
+    pub const fn full_mip_chain(self) -> Self {
+        let largest = if self.extent.width > self.extent.height {
+            self.extent.width
+        } else {
+            self.extent.height
+        };
+        self.mip_levels(u32::BITS - largest.leading_zeros())
+    }
+
+    pub const fn array_layers(self, array_layers: u32) -> Self {
+        Self {
+            array_layers,
+            ..self
+        }
+    }
+
+    pub const fn cube(self) -> Self {
+        Self {
+            cube: true,
+            array_layers: 6,
+            ..self
+        }
+    }
+
+    const fn view_type(&self) -> vk::ImageViewType {
+        match (self.cube, self.array_layers) {
+            (true, 6) => vk::ImageViewType::CUBE,
+            (true, _) => vk::ImageViewType::CUBE_ARRAY,
+            (false, 1) => vk::ImageViewType::TYPE_2D,
+            (false, _) => vk::ImageViewType::TYPE_2D_ARRAY,
         }
     }
 }
 
+#[derive(Clone, Copy, Debug)]
+pub struct State {
+    pub layout: vk::ImageLayout,
+    pub stage: vk::PipelineStageFlags2,
+    pub access: vk::AccessFlags2,
+}
+
+impl State {
+    pub const UNDEFINED: Self = Self {
+        layout: vk::ImageLayout::UNDEFINED,
+        stage: vk::PipelineStageFlags2::NONE,
+        access: vk::AccessFlags2::NONE,
+    };
+    // Freshly acquired swapchain image, synchronized with the acquire semaphore wait
+    pub const ACQUIRED: Self = Self {
+        layout: vk::ImageLayout::UNDEFINED,
+        stage: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        access: vk::AccessFlags2::NONE,
+    };
+    pub const COLOR_ATTACHMENT: Self = Self {
+        layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        stage: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        access: vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+    };
+    pub const PRESENT: Self = Self {
+        layout: vk::ImageLayout::PRESENT_SRC_KHR,
+        stage: vk::PipelineStageFlags2::NONE,
+        access: vk::AccessFlags2::NONE,
+    };
+    // Read and written by ray tracing shaders through the bindless heap
+    pub const RAY_TRACING_STORAGE: Self = Self {
+        layout: vk::ImageLayout::GENERAL,
+        stage: vk::PipelineStageFlags2::RAY_TRACING_SHADER_KHR,
+        access: vk::AccessFlags2::from_raw(
+            vk::AccessFlags2::SHADER_STORAGE_READ.as_raw()
+                | vk::AccessFlags2::SHADER_STORAGE_WRITE.as_raw(),
+        ),
+    };
+    pub const FRAGMENT_STORAGE_READ: Self = Self {
+        layout: vk::ImageLayout::GENERAL,
+        stage: vk::PipelineStageFlags2::FRAGMENT_SHADER,
+        access: vk::AccessFlags2::SHADER_STORAGE_READ,
+    };
+    pub const TRANSFER_SRC: Self = Self {
+        layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        stage: vk::PipelineStageFlags2::COPY,
+        access: vk::AccessFlags2::TRANSFER_READ,
+    };
+
+    const WRITES: vk::AccessFlags2 = vk::AccessFlags2::from_raw(
+        vk::AccessFlags2::SHADER_WRITE.as_raw()
+            | vk::AccessFlags2::SHADER_STORAGE_WRITE.as_raw()
+            | vk::AccessFlags2::COLOR_ATTACHMENT_WRITE.as_raw()
+            | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE.as_raw()
+            | vk::AccessFlags2::TRANSFER_WRITE.as_raw()
+            | vk::AccessFlags2::HOST_WRITE.as_raw()
+            | vk::AccessFlags2::MEMORY_WRITE.as_raw()
+            | vk::AccessFlags2::ACCELERATION_STRUCTURE_WRITE_KHR.as_raw(),
+    );
+
+    const fn writes(self) -> bool {
+        self.access.intersects(Self::WRITES)
+    }
+
+    // Whether `other` can follow `self` without any barrier
+    fn covers(self, other: Self) -> bool {
+        self.layout == other.layout
+            && !self.writes()
+            && !other.writes()
+            && self.stage.contains(other.stage)
+            && self.access.contains(other.access)
+    }
+}
+
 pub struct Image<const FORMAT: Format> {
+    allocation: Option<vk_mem::Allocation>,
     handle: vk::Image,
     view: vk::ImageView,
-    extent: vk::Extent2D,
+    // `FORMAT`, unless only known at runtime
+    format: vk::Format,
+    info: Info,
+    state: State,
+    // queue family whose commands last accessed the image, if any
+    owner: Option<u32>,
 }
 
 impl<const FORMAT: Format> Image<FORMAT> {
-    pub fn new(ctx: &Context, handle: vk::Image, extent: vk::Extent2D, name: &str) -> Result<Self> {
+    // Wraps an image owned elsewhere, such as by a swapchain, whose `format` may only be known at
+    // runtime
+    pub fn new(
+        ctx: &Context,
+        handle: vk::Image,
+        format: vk::Format,
+        extent: vk::Extent2D,
+        name: &str,
+    ) -> Result<Self> {
+        let info = Info::new(extent, vk::ImageUsageFlags::COLOR_ATTACHMENT);
+        let view = Self::create_view(ctx, handle, format, &info, name)?;
+
+        Ok(Self {
+            allocation: None,
+            handle,
+            view,
+            format,
+            info,
+            state: State::UNDEFINED,
+            owner: None,
+        })
+    }
+
+    pub fn create(ctx: &Context, info: Info, name: &str) -> Result<Self> {
+        if info.mip_levels == 0 || info.array_layers == 0 {
+            return Err(Error::Empty);
+        }
+        if info.cube && !info.array_layers.is_multiple_of(6) {
+            return Err(Error::CubeLayers(info.array_layers));
+        }
+        if info.cube && info.extent.width != info.extent.height {
+            return Err(Error::CubeNotSquare(info.extent));
+        }
+        if info.view_type() == vk::ImageViewType::CUBE_ARRAY
+            && !ctx.capabilities().has_image_cube_array()
+        {
+            return Err(Error::CubeArrayUnsupported);
+        }
+        let format = vk::Format::try_from(FORMAT)?;
+
+        let (handle, allocation) = {
+            let create_info = vk::ImageCreateInfo::default()
+                .flags(if info.cube {
+                    vk::ImageCreateFlags::CUBE_COMPATIBLE
+                } else {
+                    vk::ImageCreateFlags::empty()
+                })
+                .image_type(vk::ImageType::TYPE_2D)
+                .format(format)
+                .extent(vk::Extent3D {
+                    width: info.extent.width,
+                    height: info.extent.height,
+                    depth: 1,
+                })
+                .mip_levels(info.mip_levels)
+                .array_layers(info.array_layers)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .tiling(vk::ImageTiling::OPTIMAL)
+                .usage(info.usage)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE)
+                .initial_layout(vk::ImageLayout::UNDEFINED);
+
+            let allocation_info = vk_mem::AllocationCreateInfo {
+                usage: vk_mem::MemoryUsage::Auto,
+                ..Default::default()
+            };
+
+            unsafe {
+                ctx.allocator()
+                    .create_image(&create_info, &allocation_info)
+                    .map_err(Error::Create)?
+            }
+        };
+        let mut image = Self {
+            allocation: Some(allocation),
+            handle,
+            view: vk::ImageView::null(),
+            format,
+            info,
+            state: State::UNDEFINED,
+            owner: None,
+        };
+        let view = ctx
+            .set_debug_name(handle, name)
+            .map_err(Error::from)
+            .and_then(|()| Self::create_view(ctx, handle, format, &info, name));
+        match view {
+            Ok(view) => {
+                image.view = view;
+                Ok(image)
+            }
+            Err(err) => {
+                image.destroy_with(ctx);
+                Err(err)
+            }
+        }
+    }
+
+    // Size of the tightly packed texels of the first mip level and layer
+    // Color formats only
+    pub fn texels_size(&self) -> Result<vk::DeviceSize> {
+        if FORMAT.aspect_flags() != vk::ImageAspectFlags::COLOR {
+            return Err(Error::NotColor);
+        }
+        let Some(texel_size) = FORMAT.texel_size() else {
+            return Err(Error::RuntimeFormat);
+        };
+        Ok(vk::DeviceSize::from(self.info.extent.width)
+            * vk::DeviceSize::from(self.info.extent.height)
+            * texel_size as vk::DeviceSize)
+    }
+
+    // Copies the first mip level and layer into the start of `buffer`, tightly packed
+    pub fn copy_to_buffer(&mut self, recorder: &command::Recorder, buffer: &Buffer) -> Result<()> {
+        let size = self.texels_size()?;
+        if size > buffer.size() {
+            return Err(Error::BufferTooSmall {
+                size,
+                capacity: buffer.size(),
+            });
+        }
+
+        self.transition(recorder, State::TRANSFER_SRC);
+        let region = vk::BufferImageCopy::default()
+            .image_subresource(vk::ImageSubresourceLayers {
+                aspect_mask: FORMAT.aspect_flags(),
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            })
+            .image_extent(vk::Extent3D {
+                width: self.info.extent.width,
+                height: self.info.extent.height,
+                depth: 1,
+            });
+        unsafe {
+            recorder.ctx().cmd_copy_image_to_buffer(
+                **recorder,
+                self.handle,
+                State::TRANSFER_SRC.layout,
+                **buffer,
+                &[region],
+            );
+        }
+        Ok(())
+    }
+
+    pub const fn format(&self) -> vk::Format {
+        self.format
+    }
+
+    pub const fn view(&self) -> vk::ImageView {
+        self.view
+    }
+
+    pub const fn extent(&self) -> vk::Extent2D {
+        self.info.extent
+    }
+
+    pub const fn state(&self) -> State {
+        self.state
+    }
+
+    // Overrides the tracked state without a barrier, for states reached outside of command
+    // recording such as a swapchain image handed back by acquire
+    pub const fn assume(&mut self, state: State) {
+        self.state = state;
+    }
+
+    // Lets the next transition drop the current contents instead of preserving them
+    pub const fn discard(&mut self) {
+        self.state.layout = vk::ImageLayout::UNDEFINED;
+    }
+
+    pub fn transition(&mut self, recorder: &command::Recorder, to: State) {
+        if let Some(barrier) = self.barrier(recorder.family(), to) {
+            recorder.pipeline_barrier(&[barrier]);
+        }
+    }
+
+    // Barrier moving the image to `to` for use on `family`, `None` when the current state already
+    // covers it. Meant for batching several transitions into one `Recorder::pipeline_barrier`.
+    pub fn barrier(&mut self, family: u32, to: State) -> Option<vk::ImageMemoryBarrier2<'static>> {
+        debug_assert!(
+            self.state.layout == vk::ImageLayout::UNDEFINED
+                || self.owner.is_none_or(|owner| owner == family),
+            "image used on queue family {family} while owned by {:?} without a transfer",
+            self.owner,
+        );
+        self.owner = Some(family);
+
+        let from = self.state;
+        if from.covers(to) {
+            return None;
+        }
+        self.state = to;
+
+        Some(
+            vk::ImageMemoryBarrier2::default()
+                .src_stage_mask(from.stage)
+                .src_access_mask(from.access)
+                .dst_stage_mask(to.stage)
+                .dst_access_mask(to.access)
+                .old_layout(from.layout)
+                .new_layout(to.layout)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(self.handle)
+                .subresource_range(Self::subresource_range()),
+        )
+    }
+
+    // Expects the image to be in `State::COLOR_ATTACHMENT`
+    pub fn clear(&self, recorder: &command::Recorder, color: [f32; 4]) {
+        let color_attachments = [vk::RenderingAttachmentInfo::default()
+            .image_view(self.view)
+            .image_layout(State::COLOR_ATTACHMENT.layout)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .clear_value(vk::ClearValue {
+                color: vk::ClearColorValue { float32: color },
+            })];
+
+        let rendering_info = vk::RenderingInfo::default()
+            .render_area(vk::Rect2D::default().extent(self.info.extent))
+            .layer_count(1)
+            .color_attachments(&color_attachments);
+
+        let ctx = recorder.ctx();
+        unsafe {
+            ctx.cmd_begin_rendering(**recorder, &rendering_info);
+            ctx.cmd_end_rendering(**recorder);
+        }
+    }
+
+    fn create_view(
+        ctx: &Context,
+        handle: vk::Image,
+        format: vk::Format,
+        info: &Info,
+        name: &str,
+    ) -> Result<vk::ImageView> {
         let view = {
             let create_info = vk::ImageViewCreateInfo::default()
                 .image(handle)
-                .view_type(vk::ImageViewType::TYPE_2D)
-                .format(FORMAT.into())
-                .subresource_range(Self::subresource_range());
+                .view_type(info.view_type())
+                .format(format)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask: FORMAT.view_aspect_flags(),
+                    ..Self::subresource_range()
+                });
 
             unsafe {
                 ctx.create_image_view(&create_info, None)
                     .map_err(Error::CreateView)?
             }
         };
-        ctx.set_debug_name(view, &format!("{name}_image_view"))?;
-
-        Ok(Self {
-            handle,
-            view,
-            extent,
-        })
+        if let Err(err) = ctx.set_debug_name(view, &format!("{name}_image_view")) {
+            ctx.untrack(view);
+            unsafe {
+                ctx.destroy_image_view(view, None);
+            }
+            return Err(err.into());
+        }
+        Ok(view)
     }
 
     const fn subresource_range() -> vk::ImageSubresourceRange {
         vk::ImageSubresourceRange {
-            aspect_mask: Self::aspect_flags(),
+            aspect_mask: FORMAT.aspect_flags(),
             base_mip_level: 0,
             level_count: vk::REMAINING_MIP_LEVELS,
             base_array_layer: 0,
             layer_count: vk::REMAINING_ARRAY_LAYERS,
         }
     }
+}
 
-    const fn aspect_flags() -> vk::ImageAspectFlags {
-        match FORMAT {
-            Format::Hdr | Format::Swapchain => vk::ImageAspectFlags::COLOR,
-        }
+impl<const FORMAT: Format> std::ops::Deref for Image<FORMAT> {
+    type Target = vk::Image;
+    fn deref(&self) -> &Self::Target {
+        &self.handle
     }
 }
 
 impl<const FORMAT: Format> Destroy<Context> for Image<FORMAT> {
     fn destroy_with(&mut self, ctx: &Context) {
         let Self {
-            handle: _,
+            allocation,
+            handle,
             view,
-            extent: _,
+            format: _,
+            info: _,
+            state: _,
+            owner: _,
         } = self;
-        unsafe { ctx.destroy_image_view(*view, None) };
+        ctx.untrack(*view);
+        unsafe {
+            ctx.destroy_image_view(*view, None);
+            if let Some(mut allocation) = allocation.take() {
+                ctx.untrack(*handle);
+                ctx.allocator().destroy_image(*handle, &mut allocation);
+            }
+        }
+        *view = vk::ImageView::null();
+        *handle = vk::Image::null();
+    }
+}
+
+impl<const FORMAT: Format> Drop for Image<FORMAT> {
+    fn drop(&mut self) {
+        destroy::debug_assert_destroyed(self.view, "image");
     }
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
+    #[error("image format is only known at runtime")]
+    RuntimeFormat,
+    #[error("image needs at least one mip level and array layer")]
+    Empty,
+    #[error("cube map needs a multiple of 6 array layers, got {0}")]
+    CubeLayers(u32),
+    #[error("cube map faces must be square, got {0:?}")]
+    CubeNotSquare(vk::Extent2D),
+    #[error("arrays of several cube maps need the imageCubeArray feature")]
+    CubeArrayUnsupported,
+    #[error("only color images can be copied to buffers")]
+    NotColor,
+    #[error("failed to create image / {0}")]
+    Create(vk::Result),
     #[error("failed to create image view / {0}")]
     CreateView(vk::Result),
+    #[error("copy of {size} bytes exceeds buffer size {capacity}")]
+    BufferTooSmall {
+        size: vk::DeviceSize,
+        capacity: vk::DeviceSize,
+    },
     #[error("device / {0}")]
     Device(#[from] device::Error),
 }
@@ -1,3 +1,9 @@
+pub mod acceleration;
+pub mod bindless;
+pub mod buffer;
+pub mod command;
 pub mod fence;
 pub mod image;
+pub mod ray_tracing;
+pub mod sampler;
 pub mod semaphore;
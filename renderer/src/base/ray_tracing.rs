@@ -0,0 +1,607 @@
+use ash::vk;
+
+use crate::{
+    base::{
+        buffer::{self, Buffer},
+        command::Recorder,
+    },
+    context::{Context, device, properties::ShaderGroupProperties},
+    destroy::{self, Destroy},
+};
+
+type Result<T> = core::result::Result<T, Error>;
+
+mod conf {
+    pub const ENTRY_POINT: &std::ffi::CStr = c"main";
+}
+
+// SPIR-V words, entered through `main`
+pub type Shader<'a> = &'a [u32];
+
+// Triangle hit groups leave out `intersection`, procedural ones need it
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HitGroup<'a> {
+    pub closest_hit: Option<Shader<'a>>,
+    pub any_hit: Option<Shader<'a>>,
+    pub intersection: Option<Shader<'a>>,
+}
+
+impl<'a> HitGroup<'a> {
+    pub const fn triangles(closest_hit: Shader<'a>) -> Self {
+        Self {
+            closest_hit: Some(closest_hit),
+            any_hit: None,
+            intersection: None,
+        }
+    }
+
+    pub const fn procedural(intersection: Shader<'a>, closest_hit: Shader<'a>) -> Self {
+        Self {
+            closest_hit: Some(closest_hit),
+            any_hit: None,
+            intersection: Some(intersection),
+        }
+    }
+
+    pub const fn any_hit(self, any_hit: Shader<'a>) -> Self {
+        Self {
+            any_hit: Some(any_hit),
+            ..self
+        }
+    }
+}
+
+// Groups are numbered in the order raygen, miss, hit, callable, each kind in the order added
+pub struct Builder<'a> {
+    raygen: Shader<'a>,
+    miss: Vec<Shader<'a>>,
+    hit_groups: Vec<HitGroup<'a>>,
+    callables: Vec<Shader<'a>>,
+    max_recursion_depth: u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Groups {
+    miss: u32,
+    hit: u32,
+    callable: u32,
+}
+
+pub struct Pipeline {
+    groups: Groups,
+    layout: vk::PipelineLayout,
+    handle: vk::Pipeline,
+}
+
+// Hit records default to one per hit group. Adding any replaces them, so that the SBT offset of
+// an instance indexes into the added records.
+pub struct TableBuilder<'a> {
+    pipeline: &'a Pipeline,
+    hit_records: Vec<(u32, &'a [u8])>,
+}
+
+// Of a table's bytes, `count` records of `stride` bytes starting at `offset`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Region {
+    offset: usize,
+    stride: usize,
+    count: usize,
+}
+
+pub struct ShaderBindingTable {
+    buffer: Buffer,
+    raygen: vk::StridedDeviceAddressRegionKHR,
+    miss: vk::StridedDeviceAddressRegionKHR,
+    hit: vk::StridedDeviceAddressRegionKHR,
+    callable: vk::StridedDeviceAddressRegionKHR,
+}
+
+impl<'a> Builder<'a> {
+    pub const fn new(raygen: Shader<'a>) -> Self {
+        Self {
+            raygen,
+            miss: Vec::new(),
+            hit_groups: Vec::new(),
+            callables: Vec::new(),
+            max_recursion_depth: 1,
+        }
+    }
+
+    pub fn miss(mut self, miss: Shader<'a>) -> Self {
+        self.miss.push(miss);
+        self
+    }
+
+    pub fn hit_group(mut self, hit_group: HitGroup<'a>) -> Self {
+        self.hit_groups.push(hit_group);
+        self
+    }
+
+    pub fn callable(mut self, callable: Shader<'a>) -> Self {
+        self.callables.push(callable);
+        self
+    }
+
+    pub const fn max_recursion_depth(mut self, max_recursion_depth: u32) -> Self {
+        self.max_recursion_depth = max_recursion_depth;
+        self
+    }
+
+    pub fn build(self, ctx: &Context, layout: vk::PipelineLayout, name: &str) -> Result<Pipeline> {
+        let limit = ctx
+            .properties()
+            .ray_tracing_pipeline
+            .max_ray_recursion_depth;
+        if self.max_recursion_depth > limit {
+            return Err(Error::RecursionDepth {
+                depth: self.max_recursion_depth,
+                limit,
+            });
+        }
+
+        let mut modules = Vec::new();
+        let result = self.create(ctx, layout, &mut modules);
+        for module in modules {
+            unsafe {
+                ctx.destroy_shader_module(module, None);
+            }
+        }
+
+        let mut pipeline = result?;
+        if let Err(err) = ctx.set_debug_name(pipeline.handle, name) {
+            pipeline.destroy_with(ctx);
+            return Err(err.into());
+        }
+        Ok(pipeline)
+    }
+
+    fn create(
+        &self,
+        ctx: &Context,
+        layout: vk::PipelineLayout,
+        modules: &mut Vec<vk::ShaderModule>,
+    ) -> Result<Pipeline> {
+        let mut stages = Vec::new();
+        let mut stage = |code: Shader, stage: vk::ShaderStageFlags| -> Result<u32> {
+            let create_info = vk::ShaderModuleCreateInfo::default().code(code);
+            let module = unsafe {
+                ctx.create_shader_module(&create_info, None)
+                    .map_err(Error::CreateShaderModule)?
+            };
+            modules.push(module);
+            stages.push(
+                vk::PipelineShaderStageCreateInfo::default()
+                    .stage(stage)
+                    .module(module)
+                    .name(conf::ENTRY_POINT),
+            );
+            Ok(u32::try_from(stages.len() - 1).expect("Too many shader stages"))
+        };
+        let mut optional_stage = |code: Option<Shader>, kind| {
+            code.map_or(Ok(vk::SHADER_UNUSED_KHR), |code| stage(code, kind))
+        };
+
+        let mut groups = vec![general_group(optional_stage(
+            Some(self.raygen),
+            vk::ShaderStageFlags::RAYGEN_KHR,
+        )?)];
+        for &miss in &self.miss {
+            groups.push(general_group(optional_stage(
+                Some(miss),
+                vk::ShaderStageFlags::MISS_KHR,
+            )?));
+        }
+        for hit_group in &self.hit_groups {
+            let ty = if hit_group.intersection.is_some() {
+                vk::RayTracingShaderGroupTypeKHR::PROCEDURAL_HIT_GROUP
+            } else {
+                vk::RayTracingShaderGroupTypeKHR::TRIANGLES_HIT_GROUP
+            };
+            groups.push(
+                vk::RayTracingShaderGroupCreateInfoKHR::default()
+                    .ty(ty)
+                    .general_shader(vk::SHADER_UNUSED_KHR)
+                    .closest_hit_shader(optional_stage(
+                        hit_group.closest_hit,
+                        vk::ShaderStageFlags::CLOSEST_HIT_KHR,
+                    )?)
+                    .any_hit_shader(optional_stage(
+                        hit_group.any_hit,
+                        vk::ShaderStageFlags::ANY_HIT_KHR,
+                    )?)
+                    .intersection_shader(optional_stage(
+                        hit_group.intersection,
+                        vk::ShaderStageFlags::INTERSECTION_KHR,
+                    )?),
+            );
+        }
+        for &callable in &self.callables {
+            groups.push(general_group(optional_stage(
+                Some(callable),
+                vk::ShaderStageFlags::CALLABLE_KHR,
+            )?));
+        }
+
+        let create_info = vk::RayTracingPipelineCreateInfoKHR::default()
+            .stages(&stages)
+            .groups(&groups)
+            .max_pipeline_ray_recursion_depth(self.max_recursion_depth)
+            .layout(layout);
+        let handle = unsafe {
+            ctx.ext
+                .ray_tracing_pipeline
+                .create_ray_tracing_pipelines(
+                    vk::DeferredOperationKHR::null(),
+                    vk::PipelineCache::null(),
+                    &[create_info],
+                    None,
+                )
+                .map_err(|(_, err)| Error::Create(err))?[0]
+        };
+
+        let count = |shaders: usize| u32::try_from(shaders).expect("Too many shader groups");
+        Ok(Pipeline {
+            groups: Groups {
+                miss: count(self.miss.len()),
+                hit: count(self.hit_groups.len()),
+                callable: count(self.callables.len()),
+            },
+            layout,
+            handle,
+        })
+    }
+}
+
+fn general_group(shader: u32) -> vk::RayTracingShaderGroupCreateInfoKHR<'static> {
+    vk::RayTracingShaderGroupCreateInfoKHR::default()
+        .ty(vk::RayTracingShaderGroupTypeKHR::GENERAL)
+        .general_shader(shader)
+        .closest_hit_shader(vk::SHADER_UNUSED_KHR)
+        .any_hit_shader(vk::SHADER_UNUSED_KHR)
+        .intersection_shader(vk::SHADER_UNUSED_KHR)
+}
+
+impl Pipeline {
+    pub fn bind(&self, recorder: &Recorder) {
+        unsafe {
+            recorder.ctx().cmd_bind_pipeline(
+                **recorder,
+                vk::PipelineBindPoint::RAY_TRACING_KHR,
+                self.handle,
+            );
+        }
+    }
+
+    pub const fn layout(&self) -> vk::PipelineLayout {
+        self.layout
+    }
+
+    // Handles of every group in order, each `handle_size` bytes
+    fn group_handles(&self, ctx: &Context, handle_size: usize) -> Result<Vec<u8>> {
+        let group_count = 1 + self.groups.miss + self.groups.hit + self.groups.callable;
+        unsafe {
+            ctx.ext
+                .ray_tracing_pipeline
+                .get_ray_tracing_shader_group_handles(
+                    self.handle,
+                    0,
+                    group_count,
+                    group_count as usize * handle_size,
+                )
+                .map_err(Error::GroupHandles)
+        }
+    }
+}
+
+impl<'a> TableBuilder<'a> {
+    pub const fn new(pipeline: &'a Pipeline) -> Self {
+        Self {
+            pipeline,
+            hit_records: Vec::new(),
+        }
+    }
+
+    // `hit_group` counts from the first hit group, `data` follows the handle as the record's
+    // `shaderRecordEXT` buffer
+    pub fn hit_record(mut self, hit_group: u32, data: &'a [u8]) -> Self {
+        self.hit_records.push((hit_group, data));
+        self
+    }
+
+    pub fn build(self, ctx: &Context, name: &str) -> Result<ShaderBindingTable> {
+        let Self {
+            pipeline,
+            hit_records,
+        } = self;
+
+        let properties = &ctx.properties().ray_tracing_pipeline.shader_group;
+        let handles = pipeline.group_handles(ctx, properties.handle_size as usize)?;
+        let (bytes, regions) = layout(pipeline.groups, &hit_records, &handles, properties)?;
+
+        let mut buffer = Buffer::upload(ctx, buffer::Kind::ShaderBindingTable, &bytes, name)?;
+        let Some(address) = buffer.address() else {
+            buffer.destroy_with(ctx);
+            return Err(Error::NoAddress);
+        };
+        let [raygen, miss, hit, callable] = regions.map(|region| {
+            if region.count == 0 {
+                return vk::StridedDeviceAddressRegionKHR::default();
+            }
+            vk::StridedDeviceAddressRegionKHR::default()
+                .device_address(address + region.offset as vk::DeviceAddress)
+                .stride(region.stride as vk::DeviceSize)
+                .size((region.stride * region.count) as vk::DeviceSize)
+        });
+
+        Ok(ShaderBindingTable {
+            buffer,
+            raygen,
+            miss,
+            hit,
+            callable,
+        })
+    }
+}
+
+// Packs the raygen, miss, hit and callable regions one after the other out of `handles`, which
+// holds every group's handle in pipeline order
+fn layout(
+    Groups {
+        miss,
+        hit,
+        callable,
+    }: Groups,
+    hit_records: &[(u32, &[u8])],
+    handles: &[u8],
+    properties: &ShaderGroupProperties,
+) -> Result<(Vec<u8>, [Region; 4])> {
+    if let Some(&(hit_group, _)) = hit_records.iter().find(|&&(group, _)| group >= hit) {
+        return Err(Error::NoHitGroup(hit_group));
+    }
+    let default_hit_records;
+    let hit_records = if hit_records.is_empty() {
+        default_hit_records = (0..hit).map(|group| (group, &[][..])).collect::<Vec<_>>();
+        &default_hit_records
+    } else {
+        hit_records
+    };
+
+    let handle_size = properties.handle_size as usize;
+    let handle_alignment = properties.handle_alignment as usize;
+    let base_alignment = properties.base_alignment as usize;
+    let max_stride = properties.max_stride as usize;
+
+    let handle = |group: u32| {
+        let start = group as usize * handle_size;
+        &handles[start..start + handle_size]
+    };
+
+    let data_size = hit_records.iter().map(|(_, data)| data.len()).max();
+    let hit_stride = (handle_size + data_size.unwrap_or(0)).next_multiple_of(handle_alignment);
+    if hit_stride > max_stride {
+        return Err(Error::RecordTooLarge {
+            stride: hit_stride,
+            max_stride,
+        });
+    }
+    let general_stride = handle_size.next_multiple_of(handle_alignment);
+
+    // (stride, records as group and inline data), the raygen region being a single record
+    let first_miss = 1;
+    let first_hit = first_miss + miss;
+    let first_callable = first_hit + hit;
+    let records = [
+        (
+            general_stride.next_multiple_of(base_alignment),
+            vec![(0, &[][..])],
+        ),
+        (
+            general_stride,
+            (first_miss..first_hit).map(|g| (g, &[][..])).collect(),
+        ),
+        (
+            hit_stride,
+            hit_records
+                .iter()
+                .map(|&(group, data)| (first_hit + group, data))
+                .collect(),
+        ),
+        (
+            general_stride,
+            (first_callable..first_callable + callable)
+                .map(|g| (g, &[][..]))
+                .collect::<Vec<_>>(),
+        ),
+    ];
+
+    let mut regions = [Region::default(); 4];
+    let mut bytes = Vec::new();
+    for ((stride, records), region) in records.iter().zip(&mut regions) {
+        *region = Region {
+            offset: bytes.len(),
+            stride: *stride,
+            count: records.len(),
+        };
+        for &(group, data) in records {
+            let start = bytes.len();
+            bytes.extend_from_slice(handle(group));
+            bytes.extend_from_slice(data);
+            bytes.resize(start + stride, 0);
+        }
+        bytes.resize(bytes.len().next_multiple_of(base_alignment), 0);
+    }
+
+    Ok((bytes, regions))
+}
+
+impl ShaderBindingTable {
+    // Expects the pipeline the table was built from to be bound
+    pub fn trace(&self, recorder: &Recorder, extent: vk::Extent2D) {
+        unsafe {
+            recorder.ctx().ext.ray_tracing_pipeline.cmd_trace_rays(
+                **recorder,
+                &self.raygen,
+                &self.miss,
+                &self.hit,
+                &self.callable,
+                extent.width,
+                extent.height,
+                1,
+            );
+        }
+    }
+}
+
+impl std::ops::Deref for Pipeline {
+    type Target = vk::Pipeline;
+    fn deref(&self) -> &Self::Target {
+        &self.handle
+    }
+}
+
+impl Destroy<Context> for Pipeline {
+    fn destroy_with(&mut self, ctx: &Context) {
+        let Self {
+            groups: _,
+            layout: _,
+            handle,
+        } = self;
+        ctx.untrack(*handle);
+        unsafe {
+            ctx.destroy_pipeline(*handle, None);
+        }
+        *handle = vk::Pipeline::null();
+    }
+}
+
+impl Drop for Pipeline {
+    fn drop(&mut self) {
+        destroy::debug_assert_destroyed(self.handle, "ray tracing pipeline");
+    }
+}
+
+impl Destroy<Context> for ShaderBindingTable {
+    fn destroy_with(&mut self, ctx: &Context) {
+        let Self {
+            buffer,
+            raygen: _,
+            miss: _,
+            hit: _,
+            callable: _,
+        } = self;
+        buffer.destroy_with(ctx);
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("max recursion depth {depth} exceeds the device limit of {limit}")]
+    RecursionDepth { depth: u32, limit: u32 },
+    #[error("failed to create shader module / {0}")]
+    CreateShaderModule(vk::Result),
+    #[error("failed to create ray tracing pipeline / {0}")]
+    Create(vk::Result),
+    #[error("failed to get shader group handles / {0}")]
+    GroupHandles(vk::Result),
+    #[error("hit group {0} does not exist")]
+    NoHitGroup(u32),
+    #[error("hit record stride {stride} exceeds the device limit of {max_stride}")]
+    RecordTooLarge { stride: usize, max_stride: usize },
+    #[error("buffer has no device address")]
+    NoAddress,
+    #[error("buffer / {0}")]
+    Buffer(#[from] buffer::Error),
+    #[error("device / {0}")]
+    Device(#[from] device::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HANDLE_SIZE: usize = 32;
+
+    const PROPERTIES: ShaderGroupProperties = ShaderGroupProperties {
+        base_alignment: 64,
+        handle_alignment: 32,
+        handle_size: HANDLE_SIZE as u32,
+        max_stride: 4096,
+    };
+
+    const GROUPS: Groups = Groups {
+        miss: 2,
+        hit: 2,
+        callable: 0,
+    };
+
+    // Each group's handle is filled with its index plus one, to tell them apart from padding
+    fn handles(groups: Groups) -> Vec<u8> {
+        let count = 1 + groups.miss + groups.hit + groups.callable;
+        (1..=count as u8)
+            .flat_map(|byte| [byte; HANDLE_SIZE])
+            .collect()
+    }
+
+    #[test]
+    fn lays_out_regions_at_base_alignment() {
+        let (bytes, regions) = layout(GROUPS, &[], &handles(GROUPS), &PROPERTIES).unwrap();
+
+        let region = |offset, stride, count| Region {
+            offset,
+            stride,
+            count,
+        };
+        assert_eq!(
+            regions,
+            [
+                // the raygen stride is its size, which has to be aligned to the base
+                region(0, 64, 1),
+                region(64, 32, 2),
+                region(128, 32, 2),
+                region(192, 32, 0),
+            ]
+        );
+        assert_eq!(bytes.len(), 192);
+        assert_eq!(bytes[..32], [1; 32]);
+        assert_eq!(bytes[32..64], [0; 32]);
+        assert_eq!(bytes[64..128], [[2; 32], [3; 32]].concat());
+        assert_eq!(bytes[128..192], [[4; 32], [5; 32]].concat());
+    }
+
+    #[test]
+    fn places_hit_records_in_order_with_their_data() {
+        let data = [0xaa; 8];
+        let hit_records = [(1, &data[..]), (0, &[][..])];
+        let (bytes, regions) = layout(GROUPS, &hit_records, &handles(GROUPS), &PROPERTIES).unwrap();
+
+        let hit = regions[2];
+        assert_eq!((hit.offset, hit.stride, hit.count), (128, 64, 2));
+        assert_eq!(bytes.len(), 256);
+        assert_eq!(bytes[128..160], [5; 32]);
+        assert_eq!(bytes[160..168], data);
+        assert_eq!(bytes[168..192], [0; 24]);
+        assert_eq!(bytes[192..224], [4; 32]);
+    }
+
+    #[test]
+    fn rejects_missing_hit_group() {
+        let result = layout(GROUPS, &[(2, &[])], &handles(GROUPS), &PROPERTIES);
+        assert!(matches!(result, Err(Error::NoHitGroup(2))));
+    }
+
+    #[test]
+    fn rejects_records_over_max_stride() {
+        let properties = ShaderGroupProperties {
+            max_stride: 32,
+            ..PROPERTIES
+        };
+        let result = layout(GROUPS, &[(0, &[0; 4])], &handles(GROUPS), &properties);
+        assert!(matches!(
+            result,
+            Err(Error::RecordTooLarge {
+                stride: 64,
+                max_stride: 32
+            })
+        ));
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+
+use ash::vk;
+
+use crate::{
+    context::{Context, device},
+    destroy::{self, Destroy},
+};
+
+type Result<T> = core::result::Result<T, Error>;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Info {
+    pub mag_filter: vk::Filter,
+    pub min_filter: vk::Filter,
+    pub mipmap_mode: vk::SamplerMipmapMode,
+    // u, v, w
+    pub address_modes: [vk::SamplerAddressMode; 3],
+    pub border_color: vk::BorderColor,
+    // clamped to the device limit on creation
+    pub max_anisotropy: Option<f32>,
+    pub compare_op: Option<vk::CompareOp>,
+    pub min_lod: f32,
+    pub max_lod: f32,
+}
+
+impl Info {
+    // Trilinear and repeating over the whole mip chain
+    pub const fn new() -> Self {
+        Self {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            address_modes: [vk::SamplerAddressMode::REPEAT; 3],
+            border_color: vk::BorderColor::FLOAT_TRANSPARENT_BLACK,
+            max_anisotropy: None,
+            compare_op: None,
+            min_lod: 0.0,
+            max_lod: vk::LOD_CLAMP_NONE,
+        }
+    }
+
+    pub const fn nearest(self) -> Self {
+        Self {
+            mag_filter: vk::Filter::NEAREST,
+            min_filter: vk::Filter::NEAREST,
+            mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+            ..self
+        }
+    }
+
+    pub const fn filter(self, mag_filter: vk::Filter, min_filter: vk::Filter) -> Self {
+        Self {
+            mag_filter,
+            min_filter,
+            ..self
+        }
+    }
+
+    pub const fn mipmap_mode(self, mipmap_mode: vk::SamplerMipmapMode) -> Self {
+        Self {
+            mipmap_mode,
+            ..self
+        }
+    }
+
+    pub const fn address_mode(self, address_mode: vk::SamplerAddressMode) -> Self {
+        self.address_modes([address_mode; 3])
+    }
+
+    pub const fn address_modes(self, address_modes: [vk::SamplerAddressMode; 3]) -> Self {
+        Self {
+            address_modes,
+            ..self
+        }
+    }
+
+    pub const fn border_color(self, border_color: vk::BorderColor) -> Self {
+        Self {
+            border_color,
+            ..self
+        }
+    }
+
+    pub const fn anisotropy(self, max_anisotropy: f32) -> Self {
+        Self {
+            max_anisotropy: Some(max_anisotropy),
+            ..self
+        }
+    }
+
+    // For depth comparisons, e.g. shadow maps
+    pub const fn compare(self, compare_op: vk::CompareOp) -> Self {
+        Self {
+            compare_op: Some(compare_op),
+            ..self
+        }
+    }
+
+    pub const fn lod(self, min_lod: f32, max_lod: f32) -> Self {
+        Self {
+            min_lod,
+            max_lod,
+            ..self
+        }
+    }
+
+    fn clamped(self, ctx: &Context) -> Self {
+        let limit = ctx.properties().core.limits.max_sampler_anisotropy;
+        Self {
+            // anisotropy of 1 or less is the same as none
+            max_anisotropy: self
+                .max_anisotropy
+                .map(|anisotropy| anisotropy.min(limit))
+                .filter(|&anisotropy| anisotropy > 1.0),
+            ..self
+        }
+    }
+
+    // Floats compared bitwise, which is all that matters to deduplicate samplers
+    fn key(&self) -> Key {
+        let Self {
+            mag_filter,
+            min_filter,
+            mipmap_mode,
+            address_modes,
+            border_color,
+            max_anisotropy,
+            compare_op,
+            min_lod,
+            max_lod,
+        } = *self;
+        (
+            [mag_filter, min_filter],
+            mipmap_mode,
+            address_modes,
+            border_color,
+            max_anisotropy.map(f32::to_bits),
+            compare_op,
+            [min_lod.to_bits(), max_lod.to_bits()],
+        )
+    }
+}
+
+impl Default for Info {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+type Key = (
+    [vk::Filter; 2],
+    vk::SamplerMipmapMode,
+    [vk::SamplerAddressMode; 3],
+    vk::BorderColor,
+    Option<u32>,
+    Option<vk::CompareOp>,
+    [u32; 2],
+);
+
+pub struct Sampler {
+    handle: vk::Sampler,
+}
+
+// Samplers shared by every user of an identical `Info`, alive until the cache is destroyed
+#[derive(Default)]
+pub struct Cache {
+    samplers: HashMap<Key, Sampler>,
+}
+
+impl Sampler {
+    pub fn new(ctx: &Context, info: Info, name: &str) -> Result<Self> {
+        let Info {
+            mag_filter,
+            min_filter,
+            mipmap_mode,
+            address_modes: [address_mode_u, address_mode_v, address_mode_w],
+            border_color,
+            max_anisotropy,
+            compare_op,
+            min_lod,
+            max_lod,
+        } = info.clamped(ctx);
+        if min_lod > max_lod {
+            return Err(Error::LodRange { min_lod, max_lod });
+        }
+
+        let handle = {
+            let create_info = vk::SamplerCreateInfo::default()
+                .mag_filter(mag_filter)
+                .min_filter(min_filter)
+                .mipmap_mode(mipmap_mode)
+                .address_mode_u(address_mode_u)
+                .address_mode_v(address_mode_v)
+                .address_mode_w(address_mode_w)
+                .border_color(border_color)
+                .anisotropy_enable(max_anisotropy.is_some())
+                .max_anisotropy(max_anisotropy.unwrap_or(1.0))
+                .compare_enable(compare_op.is_some())
+                .compare_op(compare_op.unwrap_or(vk::CompareOp::ALWAYS))
+                .min_lod(min_lod)
+                .max_lod(max_lod);
+
+            unsafe {
+                ctx.create_sampler(&create_info, None)
+                    .map_err(Error::Create)?
+            }
+        };
+        let mut sampler = Self { handle };
+        if let Err(err) = ctx.set_debug_name(handle, name) {
+            sampler.destroy_with(ctx);
+            return Err(err.into());
+        }
+        Ok(sampler)
+    }
+}
+
+impl Cache {
+    pub fn get(&mut self, ctx: &Context, info: Info) -> Result<vk::Sampler> {
+        let info = info.clamped(ctx);
+        let key = info.key();
+        if let Some(sampler) = self.samplers.get(&key) {
+            return Ok(sampler.handle);
+        }
+
+        let sampler = Sampler::new(ctx, info, &format!("sampler#{}", self.samplers.len()))?;
+        let handle = sampler.handle;
+        self.samplers.insert(key, sampler);
+        Ok(handle)
+    }
+}
+
+impl std::ops::Deref for Sampler {
+    type Target = vk::Sampler;
+    fn deref(&self) -> &Self::Target {
+        &self.handle
+    }
+}
+
+impl Destroy<Context> for Sampler {
+    fn destroy_with(&mut self, ctx: &Context) {
+        let Self { handle } = self;
+        ctx.untrack(*handle);
+        unsafe {
+            ctx.destroy_sampler(*handle, None);
+        }
+        *handle = vk::Sampler::null();
+    }
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        destroy::debug_assert_destroyed(self.handle, "sampler");
+    }
+}
+
+impl Destroy<Context> for Cache {
+    fn destroy_with(&mut self, ctx: &Context) {
+        let Self { samplers } = self;
+        for (_, mut sampler) in samplers.drain() {
+            sampler.destroy_with(ctx);
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("min lod {min_lod} is greater than max lod {max_lod}")]
+    LodRange { min_lod: f32, max_lod: f32 },
+    #[error("failed to create sampler / {0}")]
+    Create(vk::Result),
+    #[error("device / {0}")]
+    Device(#[from] device::Error),
+}
@@ -2,7 +2,7 @@ use ash::vk;
 
 use crate::{
     context::{Context, device},
-    destroy::Destroy,
+    destroy::{self, Destroy},
 };
 
 type Result<T> = core::result::Result<T, Error>;
@@ -21,18 +21,134 @@ impl Semaphore {
                     .map_err(Error::Create)?
             }
         };
-        ctx.set_debug_name(handle, name)?;
+        let mut semaphore = Self { handle };
+        if let Err(err) = ctx.set_debug_name(handle, name) {
+            semaphore.destroy_with(ctx);
+            return Err(err.into());
+        }
+        Ok(semaphore)
+    }
+
+    pub fn submit_info(&self, stage: vk::PipelineStageFlags2) -> vk::SemaphoreSubmitInfo<'static> {
+        vk::SemaphoreSubmitInfo::default()
+            .semaphore(self.handle)
+            .stage_mask(stage)
+    }
+}
+
+// Monotonically increasing counter, signalled and waited on by value from either the host or
+// queue submissions
+pub struct Timeline {
+    handle: vk::Semaphore,
+}
+
+impl Timeline {
+    pub fn new(ctx: &Context, initial_value: u64, name: &str) -> Result<Self> {
+        let handle = {
+            let mut type_create_info = vk::SemaphoreTypeCreateInfo::default()
+                .semaphore_type(vk::SemaphoreType::TIMELINE)
+                .initial_value(initial_value);
+            let create_info = vk::SemaphoreCreateInfo::default().push_next(&mut type_create_info);
+
+            unsafe {
+                ctx.create_semaphore(&create_info, None)
+                    .map_err(Error::Create)?
+            }
+        };
+        let mut timeline = Self { handle };
+        if let Err(err) = ctx.set_debug_name(handle, name) {
+            timeline.destroy_with(ctx);
+            return Err(err.into());
+        }
+        Ok(timeline)
+    }
+
+    pub fn value(&self, ctx: &Context) -> Result<u64> {
+        unsafe {
+            ctx.get_semaphore_counter_value(self.handle)
+                .map_err(Error::Value)
+        }
+    }
+
+    pub fn signal(&self, ctx: &Context, value: u64) -> Result<()> {
+        let signal_info = vk::SemaphoreSignalInfo::default()
+            .semaphore(self.handle)
+            .value(value);
+
+        unsafe { ctx.signal_semaphore(&signal_info).map_err(Error::Signal) }
+    }
 
-        Ok(Self { handle })
+    pub fn wait(&self, ctx: &Context, value: u64) -> Result<()> {
+        let semaphores = [self.handle];
+        let values = [value];
+        let wait_info = vk::SemaphoreWaitInfo::default()
+            .semaphores(&semaphores)
+            .values(&values);
+
+        unsafe {
+            ctx.wait_semaphores(&wait_info, u64::MAX)
+                .map_err(Error::Wait)
+        }
+    }
+
+    // Device side wait or signal of `value` at `stage`, for `command::submit`
+    pub fn submit_info(
+        &self,
+        value: u64,
+        stage: vk::PipelineStageFlags2,
+    ) -> vk::SemaphoreSubmitInfo<'static> {
+        vk::SemaphoreSubmitInfo::default()
+            .semaphore(self.handle)
+            .value(value)
+            .stage_mask(stage)
+    }
+}
+
+impl std::ops::Deref for Semaphore {
+    type Target = vk::Semaphore;
+    fn deref(&self) -> &Self::Target {
+        &self.handle
     }
 }
 
 impl Destroy<Context> for Semaphore {
     fn destroy_with(&mut self, ctx: &Context) {
         let Self { handle } = self;
+        ctx.untrack(*handle);
         unsafe {
             ctx.destroy_semaphore(*handle, None);
         }
+        *handle = vk::Semaphore::null();
+    }
+}
+
+impl Drop for Semaphore {
+    fn drop(&mut self) {
+        destroy::debug_assert_destroyed(self.handle, "semaphore");
+    }
+}
+
+impl std::ops::Deref for Timeline {
+    type Target = vk::Semaphore;
+    fn deref(&self) -> &Self::Target {
+        &self.handle
+    }
+}
+
+impl Destroy<Context> for Timeline {
+    fn destroy_with(&mut self, ctx: &Context) {
+        let Self { handle } = self;
+        ctx.untrack(*handle);
+        unsafe {
+            ctx.destroy_semaphore(*handle, None);
+        }
+        *handle = vk::Semaphore::null();
+    }
+}
+
+impl Drop for Timeline {
+    fn drop(&mut self) {
+        destroy::debug_assert_destroyed(self.handle, "timeline semaphore");
     }
 }
 
@@ -40,6 +156,12 @@ impl Destroy<Context> for Semaphore {
 pub enum Error {
     #[error("failed to create semaphore / {0}")]
     Create(vk::Result),
+    #[error("failed to query semaphore value / {0}")]
+    Value(vk::Result),
+    #[error("failed to signal semaphore / {0}")]
+    Signal(vk::Result),
+    #[error("failed to wait for semaphore / {0}")]
+    Wait(vk::Result),
     #[error("device / {0}")]
     Device(#[from] device::Error),
 }
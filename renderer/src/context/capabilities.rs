@@ -0,0 +1,45 @@
+use std::{collections::HashSet, ffi::CStr};
+
+use super::features::Feature;
+
+#[derive(Clone, Debug)]
+pub struct Capabilities {
+    extensions: HashSet<&'static CStr>,
+    features: HashSet<Feature>,
+}
+
+impl Capabilities {
+    pub const fn new(extensions: HashSet<&'static CStr>, features: HashSet<Feature>) -> Self {
+        Self {
+            extensions,
+            features,
+        }
+    }
+
+    pub fn has_extension(&self, extension: &CStr) -> bool {
+        self.extensions.contains(extension)
+    }
+
+    pub fn has_feature(&self, feature: Feature) -> bool {
+        self.features.contains(&feature)
+    }
+
+    // Acceleration structures and ray tracing pipelines, both optional
+    pub fn has_ray_tracing(&self) -> bool {
+        self.has_feature(Feature::AccelerationStructure)
+            && self.has_feature(Feature::RayTracingPipeline)
+    }
+
+    // Views of more than one cube map
+    pub fn has_image_cube_array(&self) -> bool {
+        self.has_feature(Feature::ImageCubeArray)
+    }
+
+    pub const fn extensions(&self) -> &HashSet<&'static CStr> {
+        &self.extensions
+    }
+
+    pub const fn features(&self) -> &HashSet<Feature> {
+        &self.features
+    }
+}
@@ -1,45 +1,46 @@
 use std::mem::ManuallyDrop;
 
-use ash::vk;
+use ash::{ext, vk};
 
 use super::{
-    extensions, features, instance, physical_device::PhysicalDevice, queue, surface::Surface,
+    capabilities::Capabilities,
+    extensions,
+    features::{self, Feature},
+    instance,
+    physical_device::PhysicalDevice,
+    queue, registry,
 };
 
 type Result<T> = core::result::Result<T, Error>;
 
 pub struct Device {
+    capabilities: Capabilities,
     queues: queue::Queues,
     pub ext: extensions::Handles,
     allocator: ManuallyDrop<vk_mem::Allocator>,
+    registry: registry::Registry,
     handle: ash::Device,
 }
 
 impl Device {
-    pub fn new(
-        instance: &instance::Instance,
-        physical_device: &PhysicalDevice,
-        surface: &Surface,
-    ) -> Result<Self> {
-        let (queue_create_infos, queue_families) =
-            queue::Queues::create_infos(instance, physical_device, surface)?;
+    pub fn new(instance: &instance::Instance, physical_device: &PhysicalDevice) -> Result<Self> {
+        let queue_create_infos = queue::Queues::create_infos(physical_device.queue_families());
+
+        let capabilities = physical_device.capabilities().clone();
 
         let handle = {
-            let (required_features, mut additional_required_features) = features::required();
-            let mut required_features = additional_required_features
-                .iter_mut()
-                .fold(required_features, |acc_features, f| {
-                    acc_features.push_next(f.as_mut())
-                });
-
-            let extensions_to_enable = extensions::device::REQUIRED
+            let mut features_to_enable = features::Chain::enabling(capabilities.features());
+            let mut features_to_enable = features_to_enable.linked(capabilities.extensions());
+
+            let extensions_to_enable = capabilities
+                .extensions()
                 .iter()
                 .map(|e| e.as_ptr())
                 .collect::<Vec<_>>();
 
             let create_info = vk::DeviceCreateInfo::default()
                 .enabled_extension_names(&extensions_to_enable)
-                .push_next(&mut required_features)
+                .push_next(&mut features_to_enable)
                 .queue_create_infos(&queue_create_infos);
 
             unsafe {
@@ -55,8 +56,12 @@ impl Device {
             create_info.vulkan_api_version = instance::conf::VK_API_VERSION;
             create_info.flags = vk_mem::AllocatorCreateFlags::KHR_DEDICATED_ALLOCATION
                 | vk_mem::AllocatorCreateFlags::KHR_BIND_MEMORY2
-                | vk_mem::AllocatorCreateFlags::BUFFER_DEVICE_ADDRESS
-                | vk_mem::AllocatorCreateFlags::EXT_MEMORY_PRIORITY;
+                | vk_mem::AllocatorCreateFlags::BUFFER_DEVICE_ADDRESS;
+            if capabilities.has_extension(ext::memory_priority::NAME)
+                && capabilities.has_feature(Feature::MemoryPriority)
+            {
+                create_info.flags |= vk_mem::AllocatorCreateFlags::EXT_MEMORY_PRIORITY;
+            }
 
             ManuallyDrop::new(unsafe {
                 vk_mem::Allocator::new(create_info).map_err(Error::Allocator)
@@ -65,18 +70,23 @@ impl Device {
 
         let ext = extensions::Handles::new(instance, &handle);
 
-        let queues = queue::Queues::new(&handle, &queue_families);
+        let queues = queue::Queues::new(&handle, physical_device.queue_families());
 
         Ok(Self {
+            capabilities,
             queues,
             ext,
             allocator,
+            registry: registry::Registry::default(),
             handle,
         })
     }
 
+    // Also registers the object as live until `untrack`, see `report_leaks`
     #[cfg(feature = "debug-names")]
-    pub fn set_debug_name<H: vk::Handle>(&self, object: H, name: &str) -> Result<()> {
+    pub fn set_debug_name<H: vk::Handle + Copy>(&self, object: H, name: &str) -> Result<()> {
+        self.registry.insert(object, name);
+
         let object_name = std::ffi::CString::new(name).unwrap();
         let name_info = vk::DebugUtilsObjectNameInfoEXT::default()
             .object_handle(object)
@@ -91,10 +101,32 @@ impl Device {
     }
 
     #[cfg(not(feature = "debug-names"))]
-    pub fn set_debug_name<H>(&self, _: H, _: &str) -> Result<()> {
+    pub fn set_debug_name<H: vk::Handle + Copy>(&self, object: H, name: &str) -> Result<()> {
+        self.registry.insert(object, name);
         Ok(())
     }
 
+    pub fn untrack<H: vk::Handle>(&self, object: H) {
+        self.registry.remove(object);
+    }
+
+    // Logs every object named through `set_debug_name` and not untracked since, debug builds only
+    pub fn report_leaks(&self) {
+        self.registry.report();
+    }
+
+    pub const fn queues(&self) -> &queue::Queues {
+        &self.queues
+    }
+
+    pub const fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    pub fn allocator(&self) -> &vk_mem::Allocator {
+        &self.allocator
+    }
+
     pub fn wait_idle(&self) -> Result<()> {
         unsafe { self.device_wait_idle().map_err(Error::WaitIdle) }
     }
@@ -110,9 +142,11 @@ impl std::ops::Deref for Device {
 impl Drop for Device {
     fn drop(&mut self) {
         let Self {
+            capabilities: _,
             queues: _,
             ext: _,
             allocator,
+            registry: _,
             handle,
         } = self;
         unsafe {
@@ -125,12 +159,15 @@ impl Drop for Device {
 impl core::fmt::Debug for Device {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let Self {
+            capabilities,
             queues,
             ext: _,
             allocator: _,
+            registry: _,
             handle: _,
         } = self;
         f.debug_struct("Device")
+            .field("capabilities", capabilities)
             .field("queues", queues)
             .finish_non_exhaustive()
     }
@@ -140,8 +177,6 @@ impl core::fmt::Debug for Device {
 pub enum Error {
     #[error("failed to create device / {0}")]
     Create(vk::Result),
-    #[error("queue / {0}")]
-    Queue(#[from] queue::Error),
     #[error("allocator / {0}")]
     Allocator(vk::Result),
     #[error("failed to set debug name / {0}")]
@@ -2,13 +2,24 @@ use ash::{ext, khr};
 
 pub mod instance {
     use ash::{ext, khr};
+    use raw_window_handle::RawDisplayHandle;
 
-    pub const REQUIRED: &[*const std::ffi::c_char] = &[
-        ext::debug_utils::NAME.as_ptr(),
-        // Surface
-        khr::surface::NAME.as_ptr(),
-        khr::win32_surface::NAME.as_ptr(),
-    ];
+    pub const REQUIRED: &[&std::ffi::CStr] = &[ext::debug_utils::NAME];
+
+    pub const SURFACE: &std::ffi::CStr = khr::surface::NAME;
+
+    // Enabled when available, needed for surfaces to report HDR color spaces
+    pub const OPTIONAL_SURFACE: &[&std::ffi::CStr] = &[ext::swapchain_colorspace::NAME];
+
+    pub const fn surface_for(display: RawDisplayHandle) -> Option<&'static std::ffi::CStr> {
+        match display {
+            RawDisplayHandle::Windows(_) => Some(khr::win32_surface::NAME),
+            RawDisplayHandle::Xlib(_) => Some(khr::xlib_surface::NAME),
+            RawDisplayHandle::Xcb(_) => Some(khr::xcb_surface::NAME),
+            RawDisplayHandle::Wayland(_) => Some(khr::wayland_surface::NAME),
+            _ => None,
+        }
+    }
 }
 
 pub mod device {
@@ -20,24 +31,36 @@ pub mod device {
 
     type Result<T> = core::result::Result<T, Error>;
 
-    // TODO: split into required and optional extensions
-    pub const REQUIRED: &[&std::ffi::CStr] = &[
-        // Surface
-        khr::swapchain::NAME,
+    pub const PRESENTATION: &[&std::ffi::CStr] = &[khr::swapchain::NAME];
+
+    pub const REQUIRED: &[&std::ffi::CStr] = &[];
+
+    // (extension, extensions it depends on), dependencies listed before their dependents
+    pub const OPTIONAL: &[(&std::ffi::CStr, &[&std::ffi::CStr])] = &[
         // Acceleration Structure
-        khr::acceleration_structure::NAME,
-        khr::deferred_host_operations::NAME,
+        (khr::deferred_host_operations::NAME, &[]),
+        (
+            khr::acceleration_structure::NAME,
+            &[khr::deferred_host_operations::NAME],
+        ),
         // Ray Tracing
-        khr::ray_tracing_pipeline::NAME,
-        // Additional
-        ext::memory_priority::NAME,
-        ext::pageable_device_local_memory::NAME,
+        (
+            khr::ray_tracing_pipeline::NAME,
+            &[khr::acceleration_structure::NAME],
+        ),
+        (ext::memory_priority::NAME, &[]),
+        (
+            ext::pageable_device_local_memory::NAME,
+            &[ext::memory_priority::NAME],
+        ),
     ];
 
-    pub fn supported_by(
+    pub fn select(
         instance: &instance::Instance,
         physical_device: vk::PhysicalDevice,
-    ) -> Result<bool> {
+        presentable: bool,
+    ) -> Result<core::result::Result<HashSet<&'static std::ffi::CStr>, Vec<&'static std::ffi::CStr>>>
+    {
         let available = unsafe {
             instance
                 .enumerate_device_extension_properties(physical_device)
@@ -50,7 +73,28 @@ pub mod device {
             .collect::<core::result::Result<_, _>>()
             .map_err(Error::Parse)?;
 
-        Ok(REQUIRED.iter().all(|required| available.contains(required)))
+        let mut enabled = REQUIRED
+            .iter()
+            .chain(if presentable { PRESENTATION } else { &[] })
+            .copied()
+            .collect::<HashSet<_>>();
+
+        let missing = enabled
+            .iter()
+            .copied()
+            .filter(|required| !available.contains(required))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            return Ok(Err(missing));
+        }
+
+        for (optional, dependencies) in OPTIONAL {
+            if available.contains(optional) && dependencies.iter().all(|d| enabled.contains(d)) {
+                enabled.insert(optional);
+            }
+        }
+
+        Ok(Ok(enabled))
     }
 
     #[derive(Debug, thiserror::Error)]
@@ -63,16 +107,22 @@ pub mod device {
 }
 
 pub struct Handles {
+    pub acceleration_structure: khr::acceleration_structure::Device,
     pub debug_utils: ext::debug_utils::Device,
+    pub ray_tracing_pipeline: khr::ray_tracing_pipeline::Device,
     pub swapchain: khr::swapchain::Device,
 }
 
 impl Handles {
     pub fn new(instance: &super::instance::Instance, device: &ash::Device) -> Self {
+        let acceleration_structure = khr::acceleration_structure::Device::new(instance, device);
         let debug_utils = ext::debug_utils::Device::new(instance, device);
+        let ray_tracing_pipeline = khr::ray_tracing_pipeline::Device::new(instance, device);
         let swapchain = khr::swapchain::Device::new(instance, device);
         Self {
+            acceleration_structure,
             debug_utils,
+            ray_tracing_pipeline,
             swapchain,
         }
     }
@@ -1,102 +1,264 @@
-use ash::vk;
+use std::collections::HashSet;
 
-use super::instance;
-
-pub fn supported_by(instance: &instance::Instance, physical_device: vk::PhysicalDevice) -> bool {
-    let mut pageable_device_local_memory =
-        vk::PhysicalDevicePageableDeviceLocalMemoryFeaturesEXT::default();
-    let mut memory_priority = vk::PhysicalDeviceMemoryPriorityFeaturesEXT::default();
-
-    let mut ray_tracing_pipeline = vk::PhysicalDeviceRayTracingPipelineFeaturesKHR::default();
-    let mut acceleration_structure = vk::PhysicalDeviceAccelerationStructureFeaturesKHR::default();
+use ash::{ext, khr, vk};
 
-    let mut v_1_3 = vk::PhysicalDeviceVulkan13Features::default();
-    let mut v_1_2 = vk::PhysicalDeviceVulkan12Features::default();
-    let mut v_1_1 = vk::PhysicalDeviceVulkan11Features::default();
-
-    let mut v_1_0 = vk::PhysicalDeviceFeatures2::default()
-        .push_next(&mut pageable_device_local_memory)
-        .push_next(&mut memory_priority)
-        .push_next(&mut ray_tracing_pipeline)
-        .push_next(&mut acceleration_structure)
-        .push_next(&mut v_1_3)
-        .push_next(&mut v_1_2)
-        .push_next(&mut v_1_1);
-
-    unsafe { instance.get_physical_device_features2(physical_device, &mut v_1_0) };
+use super::instance;
 
-    v_1_0.features.sampler_anisotropy > 0
-    && v_1_0.features.shader_int64 > 0
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Feature {
+    // 1.0
+    ImageCubeArray,
+    SamplerAnisotropy,
+    ShaderInt64,
     // 1.1
-    && v_1_1.storage_buffer16_bit_access > 0
-    && v_1_1.uniform_and_storage_buffer16_bit_access > 0
-
+    StorageBuffer16BitAccess,
+    UniformAndStorageBuffer16BitAccess,
     // 1.2
-    && v_1_2.buffer_device_address > 0
-    && v_1_2.descriptor_binding_partially_bound > 0
-    && v_1_2.descriptor_binding_variable_descriptor_count > 0
-    && v_1_2.runtime_descriptor_array > 0
-    && v_1_2.scalar_block_layout > 0
-    && v_1_2.uniform_and_storage_buffer8_bit_access > 0
-    && v_1_2.vulkan_memory_model > 0
+    BufferDeviceAddress,
+    DescriptorBindingPartiallyBound,
+    DescriptorBindingSampledImageUpdateAfterBind,
+    DescriptorBindingStorageBufferUpdateAfterBind,
+    DescriptorBindingStorageImageUpdateAfterBind,
+    DescriptorBindingUpdateUnusedWhilePending,
+    DescriptorBindingVariableDescriptorCount,
+    DescriptorIndexing,
+    RuntimeDescriptorArray,
+    ScalarBlockLayout,
+    ShaderSampledImageArrayNonUniformIndexing,
+    ShaderStorageBufferArrayNonUniformIndexing,
+    ShaderStorageImageArrayNonUniformIndexing,
+    TimelineSemaphore,
+    UniformAndStorageBuffer8BitAccess,
+    VulkanMemoryModel,
     // 1.3
-    && v_1_3.dynamic_rendering > 0
-    && v_1_3.synchronization2 > 0
+    DynamicRendering,
+    Synchronization2,
     // acceleration structure
-    && acceleration_structure.acceleration_structure > 0
+    AccelerationStructure,
     // ray tracing pipeline
-    && ray_tracing_pipeline.ray_tracing_pipeline > 0
+    RayTracingPipeline,
     // memory priority
-    && memory_priority.memory_priority > 0
+    MemoryPriority,
     // pageable device local memory
-    && pageable_device_local_memory.pageable_device_local_memory > 0
+    PageableDeviceLocalMemory,
+}
+
+impl Feature {
+    pub const REQUIRED: &[Self] = &[
+        Self::SamplerAnisotropy,
+        Self::ShaderInt64,
+        Self::StorageBuffer16BitAccess,
+        Self::UniformAndStorageBuffer16BitAccess,
+        Self::BufferDeviceAddress,
+        Self::DescriptorBindingPartiallyBound,
+        Self::DescriptorBindingSampledImageUpdateAfterBind,
+        Self::DescriptorBindingStorageBufferUpdateAfterBind,
+        Self::DescriptorBindingStorageImageUpdateAfterBind,
+        Self::DescriptorBindingUpdateUnusedWhilePending,
+        Self::DescriptorBindingVariableDescriptorCount,
+        Self::DescriptorIndexing,
+        Self::RuntimeDescriptorArray,
+        Self::ScalarBlockLayout,
+        Self::ShaderSampledImageArrayNonUniformIndexing,
+        Self::ShaderStorageBufferArrayNonUniformIndexing,
+        Self::ShaderStorageImageArrayNonUniformIndexing,
+        Self::TimelineSemaphore,
+        Self::UniformAndStorageBuffer8BitAccess,
+        Self::VulkanMemoryModel,
+        Self::DynamicRendering,
+        Self::Synchronization2,
+    ];
+
+    // Ray tracing is left out on devices without it, such as software rasterizers
+    pub const OPTIONAL: &[Self] = &[
+        Self::ImageCubeArray,
+        Self::AccelerationStructure,
+        Self::RayTracingPipeline,
+        Self::MemoryPriority,
+        Self::PageableDeviceLocalMemory,
+    ];
+
+    const fn extension(self) -> Option<&'static std::ffi::CStr> {
+        match self {
+            Self::AccelerationStructure => Some(khr::acceleration_structure::NAME),
+            Self::RayTracingPipeline => Some(khr::ray_tracing_pipeline::NAME),
+            Self::MemoryPriority => Some(ext::memory_priority::NAME),
+            Self::PageableDeviceLocalMemory => Some(ext::pageable_device_local_memory::NAME),
+            _ => None,
+        }
+    }
+
+    const fn flag(self, chain: &mut Chain) -> &mut vk::Bool32 {
+        match self {
+            Self::ImageCubeArray => &mut chain.v_1_0.image_cube_array,
+            Self::SamplerAnisotropy => &mut chain.v_1_0.sampler_anisotropy,
+            Self::ShaderInt64 => &mut chain.v_1_0.shader_int64,
+            Self::StorageBuffer16BitAccess => &mut chain.v_1_1.storage_buffer16_bit_access,
+            Self::UniformAndStorageBuffer16BitAccess => {
+                &mut chain.v_1_1.uniform_and_storage_buffer16_bit_access
+            }
+            Self::BufferDeviceAddress => &mut chain.v_1_2.buffer_device_address,
+            Self::DescriptorBindingPartiallyBound => {
+                &mut chain.v_1_2.descriptor_binding_partially_bound
+            }
+            Self::DescriptorBindingSampledImageUpdateAfterBind => {
+                &mut chain
+                    .v_1_2
+                    .descriptor_binding_sampled_image_update_after_bind
+            }
+            Self::DescriptorBindingStorageBufferUpdateAfterBind => {
+                &mut chain
+                    .v_1_2
+                    .descriptor_binding_storage_buffer_update_after_bind
+            }
+            Self::DescriptorBindingStorageImageUpdateAfterBind => {
+                &mut chain
+                    .v_1_2
+                    .descriptor_binding_storage_image_update_after_bind
+            }
+            Self::DescriptorBindingUpdateUnusedWhilePending => {
+                &mut chain.v_1_2.descriptor_binding_update_unused_while_pending
+            }
+            Self::DescriptorBindingVariableDescriptorCount => {
+                &mut chain.v_1_2.descriptor_binding_variable_descriptor_count
+            }
+            Self::DescriptorIndexing => &mut chain.v_1_2.descriptor_indexing,
+            Self::RuntimeDescriptorArray => &mut chain.v_1_2.runtime_descriptor_array,
+            Self::ScalarBlockLayout => &mut chain.v_1_2.scalar_block_layout,
+            Self::ShaderSampledImageArrayNonUniformIndexing => {
+                &mut chain.v_1_2.shader_sampled_image_array_non_uniform_indexing
+            }
+            Self::ShaderStorageBufferArrayNonUniformIndexing => {
+                &mut chain.v_1_2.shader_storage_buffer_array_non_uniform_indexing
+            }
+            Self::ShaderStorageImageArrayNonUniformIndexing => {
+                &mut chain.v_1_2.shader_storage_image_array_non_uniform_indexing
+            }
+            Self::TimelineSemaphore => &mut chain.v_1_2.timeline_semaphore,
+            Self::UniformAndStorageBuffer8BitAccess => {
+                &mut chain.v_1_2.uniform_and_storage_buffer8_bit_access
+            }
+            Self::VulkanMemoryModel => &mut chain.v_1_2.vulkan_memory_model,
+            Self::DynamicRendering => &mut chain.v_1_3.dynamic_rendering,
+            Self::Synchronization2 => &mut chain.v_1_3.synchronization2,
+            Self::AccelerationStructure => &mut chain.acceleration_structure.acceleration_structure,
+            Self::RayTracingPipeline => &mut chain.ray_tracing_pipeline.ray_tracing_pipeline,
+            Self::MemoryPriority => &mut chain.memory_priority.memory_priority,
+            Self::PageableDeviceLocalMemory => {
+                &mut chain
+                    .pageable_device_local_memory
+                    .pageable_device_local_memory
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Chain {
+    v_1_0: vk::PhysicalDeviceFeatures,
+    v_1_1: vk::PhysicalDeviceVulkan11Features<'static>,
+    v_1_2: vk::PhysicalDeviceVulkan12Features<'static>,
+    v_1_3: vk::PhysicalDeviceVulkan13Features<'static>,
+    acceleration_structure: vk::PhysicalDeviceAccelerationStructureFeaturesKHR<'static>,
+    ray_tracing_pipeline: vk::PhysicalDeviceRayTracingPipelineFeaturesKHR<'static>,
+    memory_priority: vk::PhysicalDeviceMemoryPriorityFeaturesEXT<'static>,
+    pageable_device_local_memory: vk::PhysicalDevicePageableDeviceLocalMemoryFeaturesEXT<'static>,
 }
 
-pub fn required<'a>() -> (
-    vk::PhysicalDeviceFeatures2<'a>,
-    [Box<dyn vk::ExtendsPhysicalDeviceFeatures2>; 7],
-) {
-    (
-        vk::PhysicalDeviceFeatures2::default().features(
-            vk::PhysicalDeviceFeatures::default()
-                .sampler_anisotropy(true)
-                .shader_int64(true),
-        ),
-        [
-            Box::new(
-                vk::PhysicalDeviceVulkan11Features::default()
-                    .storage_buffer16_bit_access(true)
-                    .uniform_and_storage_buffer16_bit_access(true),
-            ),
-            Box::new(
-                vk::PhysicalDeviceVulkan12Features::default()
-                    .buffer_device_address(true)
-                    .descriptor_binding_partially_bound(true)
-                    .descriptor_binding_variable_descriptor_count(true)
-                    .descriptor_indexing(true)
-                    .runtime_descriptor_array(true)
-                    .scalar_block_layout(true)
-                    .uniform_and_storage_buffer8_bit_access(true)
-                    .vulkan_memory_model(true),
-            ),
-            Box::new(
-                vk::PhysicalDeviceVulkan13Features::default()
-                    .dynamic_rendering(true)
-                    .synchronization2(true),
-            ),
-            Box::new(
-                vk::PhysicalDeviceAccelerationStructureFeaturesKHR::default()
-                    .acceleration_structure(true),
-            ),
-            Box::new(
-                vk::PhysicalDeviceRayTracingPipelineFeaturesKHR::default()
-                    .ray_tracing_pipeline(true),
-            ),
-            Box::new(vk::PhysicalDeviceMemoryPriorityFeaturesEXT::default().memory_priority(true)),
-            Box::new(
-                vk::PhysicalDevicePageableDeviceLocalMemoryFeaturesEXT::default()
-                    .pageable_device_local_memory(true),
-            ),
-        ],
-    )
+impl Chain {
+    pub fn enabling(features: &HashSet<Feature>) -> Self {
+        let mut chain = Self::default();
+        for feature in features {
+            *feature.flag(&mut chain) = vk::TRUE;
+        }
+        chain
+    }
+
+    fn supported_by(
+        instance: &instance::Instance,
+        physical_device: vk::PhysicalDevice,
+        extensions: &HashSet<&std::ffi::CStr>,
+    ) -> Self {
+        let mut chain = Self::default();
+        let mut features = chain.linked(extensions);
+        unsafe { instance.get_physical_device_features2(physical_device, &mut features) };
+        chain.v_1_0 = features.features;
+        chain
+    }
+
+    // Leaves out the structs of extensions missing from `extensions`, whose flags stay false
+    pub fn linked(
+        &mut self,
+        extensions: &HashSet<&std::ffi::CStr>,
+    ) -> vk::PhysicalDeviceFeatures2<'_> {
+        let Self {
+            v_1_0,
+            v_1_1,
+            v_1_2,
+            v_1_3,
+            acceleration_structure,
+            ray_tracing_pipeline,
+            memory_priority,
+            pageable_device_local_memory,
+        } = self;
+
+        v_1_1.p_next = std::ptr::null_mut();
+        v_1_2.p_next = std::ptr::null_mut();
+        v_1_3.p_next = std::ptr::null_mut();
+        acceleration_structure.p_next = std::ptr::null_mut();
+        ray_tracing_pipeline.p_next = std::ptr::null_mut();
+        memory_priority.p_next = std::ptr::null_mut();
+        pageable_device_local_memory.p_next = std::ptr::null_mut();
+
+        let mut features = vk::PhysicalDeviceFeatures2::default()
+            .features(*v_1_0)
+            .push_next(v_1_1)
+            .push_next(v_1_2)
+            .push_next(v_1_3);
+        if extensions.contains(khr::acceleration_structure::NAME) {
+            features = features.push_next(acceleration_structure);
+        }
+        if extensions.contains(khr::ray_tracing_pipeline::NAME) {
+            features = features.push_next(ray_tracing_pipeline);
+        }
+        if extensions.contains(ext::memory_priority::NAME) {
+            features = features.push_next(memory_priority);
+        }
+        if extensions.contains(ext::pageable_device_local_memory::NAME) {
+            features = features.push_next(pageable_device_local_memory);
+        }
+        features
+    }
+}
+
+pub fn select(
+    instance: &instance::Instance,
+    physical_device: vk::PhysicalDevice,
+    extensions: &HashSet<&std::ffi::CStr>,
+) -> Result<HashSet<Feature>, Vec<Feature>> {
+    let mut supported = Chain::supported_by(instance, physical_device, extensions);
+    let mut is_supported = |feature: Feature| {
+        *feature.flag(&mut supported) == vk::TRUE
+            && feature.extension().is_none_or(|e| extensions.contains(e))
+    };
+
+    let missing = Feature::REQUIRED
+        .iter()
+        .copied()
+        .filter(|&f| !is_supported(f))
+        .collect::<Vec<_>>();
+    if !missing.is_empty() {
+        return Err(missing);
+    }
+
+    Ok(Feature::REQUIRED
+        .iter()
+        .copied()
+        .chain(
+            Feature::OPTIONAL
+                .iter()
+                .copied()
+                .filter(|&f| is_supported(f)),
+        )
+        .collect())
 }
@@ -1,6 +1,9 @@
+use std::mem::ManuallyDrop;
+
 use ash::{Entry, vk};
+use raw_window_handle::HasDisplayHandle;
 
-use super::extensions;
+use super::{extensions, validation};
 
 pub mod conf {
     pub const APPLICATION_NAME: &std::ffi::CStr = c"RAYGE Renderer";
@@ -10,22 +13,71 @@ pub mod conf {
 type Result<T> = core::result::Result<T, Error>;
 
 pub struct Instance {
-    instance: ash::Instance,
+    messenger: ManuallyDrop<Option<validation::Messenger>>,
+    handle: ash::Instance,
     pub entry: Entry,
 }
 
 impl Instance {
-    pub fn new() -> Result<Self> {
+    pub fn new(display: &impl HasDisplayHandle) -> Result<Self> {
+        let platform_surface =
+            extensions::instance::surface_for(display.display_handle()?.as_raw())
+                .ok_or(Error::UnsupportedPlatform)?;
+
+        Self::create(
+            &[extensions::instance::SURFACE, platform_surface],
+            extensions::instance::OPTIONAL_SURFACE,
+        )
+    }
+
+    pub fn new_headless() -> Result<Self> {
+        Self::create(&[], &[])
+    }
+
+    fn create(
+        surface_extensions: &[&std::ffi::CStr],
+        optional_extensions: &[&std::ffi::CStr],
+    ) -> Result<Self> {
         let entry = unsafe { Entry::load()? };
 
-        let instance = {
+        let validation_layer = validation::layer(&entry)?;
+        let available = unsafe {
+            entry
+                .enumerate_instance_extension_properties(None)
+                .map_err(Error::EnumerateExtensions)?
+        };
+        let optional_extensions = optional_extensions.iter().filter(|&&optional| {
+            available
+                .iter()
+                .any(|extension| extension.extension_name_as_c_str() == Ok(optional))
+        });
+
+        let handle = {
             let app_info = vk::ApplicationInfo::default()
                 .application_name(conf::APPLICATION_NAME)
                 .api_version(conf::VK_API_VERSION);
 
-            let create_info = vk::InstanceCreateInfo::default()
+            let extensions_to_enable = extensions::instance::REQUIRED
+                .iter()
+                .chain(surface_extensions)
+                .chain(optional_extensions)
+                .map(|e| e.as_ptr())
+                .collect::<Vec<_>>();
+
+            let layers_to_enable = validation_layer
+                .iter()
+                .map(|l| l.as_ptr())
+                .collect::<Vec<_>>();
+
+            let mut messenger_create_info = validation::Messenger::create_info();
+
+            let mut create_info = vk::InstanceCreateInfo::default()
                 .application_info(&app_info)
-                .enabled_extension_names(extensions::instance::REQUIRED);
+                .enabled_extension_names(&extensions_to_enable)
+                .enabled_layer_names(&layers_to_enable);
+            if validation_layer.is_some() {
+                create_info = create_info.push_next(&mut messenger_create_info);
+            }
 
             unsafe {
                 entry
@@ -34,15 +86,28 @@ impl Instance {
             }
         };
 
-        Ok(Self { instance, entry })
+        let messenger = validation_layer
+            .map(|_| validation::Messenger::new(&entry, &handle))
+            .transpose()?;
+
+        Ok(Self {
+            messenger: ManuallyDrop::new(messenger),
+            handle,
+            entry,
+        })
     }
 }
 
 impl Drop for Instance {
     fn drop(&mut self) {
-        let Self { instance, entry: _ } = self;
+        let Self {
+            messenger,
+            handle,
+            entry: _,
+        } = self;
         unsafe {
-            instance.destroy_instance(None);
+            ManuallyDrop::drop(messenger);
+            handle.destroy_instance(None);
         }
     }
 }
@@ -50,7 +115,7 @@ impl Drop for Instance {
 impl std::ops::Deref for Instance {
     type Target = ash::Instance;
     fn deref(&self) -> &Self::Target {
-        &self.instance
+        &self.handle
     }
 }
 
@@ -58,6 +123,14 @@ impl std::ops::Deref for Instance {
 pub enum Error {
     #[error("failed to load vulkan entry-point / {0}")]
     LoadEntry(#[from] ash::LoadingError),
+    #[error("failed to get display handle / {0}")]
+    GetDisplayHandle(#[from] raw_window_handle::HandleError),
+    #[error("unsupported windowing platform")]
+    UnsupportedPlatform,
+    #[error("failed to enumerate instance extensions / {0}")]
+    EnumerateExtensions(vk::Result),
     #[error("failed to create vulkan instance / {0}")]
     Create(vk::Result),
+    #[error("validation / {0}")]
+    Validation(#[from] validation::Error),
 }
@@ -1,37 +1,65 @@
+mod capabilities;
 pub mod device;
 mod extensions;
 mod features;
 mod instance;
 mod physical_device;
-mod properties;
-mod queue;
+pub mod properties;
+pub mod queue;
+mod registry;
 pub mod surface;
+mod validation;
 
-use raw_window_handle::HasWindowHandle;
+use std::sync::{Mutex, MutexGuard};
+
+use ash::vk;
+use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
+
+use crate::{base::fence, destroy::Destroy};
 
 type Result<T> = core::result::Result<T, Error>;
 
 pub struct Context {
+    fences: Mutex<fence::Pool>,
     device: device::Device,
-    pub surface: surface::Surface,
+    pub surface: Option<surface::Surface>,
     physical_device: physical_device::PhysicalDevice,
     _instance: instance::Instance,
 }
 
 impl Context {
-    pub(super) fn new(window: &impl HasWindowHandle) -> Result<Self> {
-        let instance = instance::Instance::new()?;
+    pub(super) fn new(
+        window: &(impl HasWindowHandle + HasDisplayHandle),
+        window_extent: vk::Extent2D,
+    ) -> Result<Self> {
+        let instance = instance::Instance::new(window)?;
 
-        let surface_handle = surface::Handle::new(&instance, window)?;
+        let surface_handle = surface::Handle::new(&instance, window, window_extent)?;
+
+        Self::create(instance, Some(surface_handle))
+    }
+
+    pub(super) fn new_headless() -> Result<Self> {
+        let instance = instance::Instance::new_headless()?;
+
+        Self::create(instance, None)
+    }
 
+    fn create(
+        instance: instance::Instance,
+        surface_handle: Option<surface::Handle>,
+    ) -> Result<Self> {
         let (physical_device, surface_config) =
-            physical_device::PhysicalDevice::new(&instance, &surface_handle)?;
+            physical_device::PhysicalDevice::new(&instance, surface_handle.as_ref())?;
 
-        let surface = surface::Surface::new(surface_config, surface_handle);
+        let surface = surface_handle
+            .zip(surface_config)
+            .map(|(handle, config)| surface::Surface::new(config, handle));
 
-        let device = device::Device::new(&instance, &physical_device, &surface)?;
+        let device = device::Device::new(&instance, &physical_device)?;
 
         let context = Self {
+            fences: Mutex::default(),
             device,
             surface,
             physical_device,
@@ -43,8 +71,19 @@ impl Context {
         Ok(context)
     }
 
+    pub const fn properties(&self) -> &properties::Properties {
+        self.physical_device.properties()
+    }
+
+    pub fn fences(&self) -> MutexGuard<'_, fence::Pool> {
+        self.fences.lock().expect("Fence pool lock poisoned")
+    }
+
     pub fn refresh_surface_capabilities(&mut self) -> Result<bool> {
-        Ok(self.surface.refresh_capabilities(&self.physical_device)?)
+        Ok(match &mut self.surface {
+            Some(surface) => surface.refresh_capabilities(&self.physical_device)?,
+            None => true,
+        })
     }
 }
 
@@ -55,9 +94,18 @@ impl std::ops::Deref for Context {
     }
 }
 
+impl Drop for Context {
+    fn drop(&mut self) {
+        let mut fences = std::mem::take(self.fences.get_mut().expect("Fence pool lock poisoned"));
+        fences.destroy_with(self);
+        self.device.report_leaks();
+    }
+}
+
 impl core::fmt::Debug for Context {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let Self {
+            fences: _,
             device,
             surface,
             physical_device,
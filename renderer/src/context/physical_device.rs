@@ -1,47 +1,208 @@
 use ash::vk;
 
-use super::{extensions, features, instance::Instance, properties::Properties, surface};
+use super::{
+    capabilities::Capabilities,
+    extensions,
+    features::{self, Feature},
+    instance::Instance,
+    properties::{CoreProperties, Properties},
+    queue, surface,
+};
 
 type Result<T> = core::result::Result<T, Error>;
 
+type Candidate = (PhysicalDevice, Option<surface::Config>);
+
 pub struct PhysicalDevice {
     properties: Properties,
+    capabilities: Capabilities,
+    queue_families: queue::Families,
     handle: vk::PhysicalDevice,
 }
 
+#[derive(Debug)]
+pub struct Rejection {
+    pub name: String,
+    pub reasons: Vec<Reason>,
+}
+
+#[derive(Debug)]
+pub enum Reason {
+    MissingExtensions(Vec<&'static std::ffi::CStr>),
+    MissingFeatures(Vec<Feature>),
+    NoSurfaceFormat,
+    MissingQueueFamilies(Vec<&'static str>),
+}
+
+#[derive(Debug)]
+pub struct Rejections(Vec<Rejection>);
+
 impl PhysicalDevice {
-    pub fn new(instance: &Instance, surface: &surface::Handle) -> Result<(Self, surface::Config)> {
+    pub fn new(instance: &Instance, surface: Option<&surface::Handle>) -> Result<Candidate> {
         let possible_physical_devices =
             unsafe { instance.enumerate_physical_devices() }.map_err(Error::Enumerate)?;
 
-        for physical_device in possible_physical_devices {
-            if let Some(result) = Self::try_create(instance, physical_device, surface)? {
-                return Ok(result);
+        let mut candidates = Vec::new();
+        let mut rejections = Vec::new();
+        for (index, handle) in possible_physical_devices.into_iter().enumerate() {
+            let core_properties = CoreProperties::get(instance, handle);
+            let identity = selection::Identity::new(index, &core_properties);
+            match Self::try_create(instance, handle, surface)? {
+                Ok(candidate) => {
+                    let score = selection::Score::new(&core_properties);
+                    tracing::info!("Suitable physical device {identity} / {score:?}");
+                    candidates.push((identity, score, candidate));
+                }
+                Err(reasons) => {
+                    let rejection = Rejection {
+                        name: identity.name.clone(),
+                        reasons,
+                    };
+                    tracing::info!("Rejected physical device {rejection}");
+                    rejections.push((identity, rejection));
+                }
             }
         }
 
-        Err(Error::NoSuitableCandidate)
+        let preferred = selection::Preference::get().and_then(|preference| {
+            let preferred = candidates
+                .iter()
+                .position(|(identity, _, _)| preference.matches(identity));
+            if preferred.is_none() {
+                if let Some((identity, rejection)) = rejections
+                    .iter()
+                    .find(|(identity, _)| preference.matches(identity))
+                {
+                    tracing::warn!(
+                        "Preferred physical device {identity} was rejected / {rejection}"
+                    );
+                } else {
+                    tracing::warn!("No physical device matches preference {preference:?}");
+                }
+            }
+            preferred
+        });
+
+        let chosen = preferred.or_else(|| {
+            candidates
+                .iter()
+                .enumerate()
+                .max_by_key(|(_, (_, score, _))| *score)
+                .map(|(idx, _)| idx)
+        });
+
+        let Some(chosen) = chosen else {
+            return Err(Error::NoSuitableCandidate(Rejections(
+                rejections
+                    .into_iter()
+                    .map(|(_, rejection)| rejection)
+                    .collect(),
+            )));
+        };
+
+        let (identity, _, chosen) = candidates.swap_remove(chosen);
+        tracing::info!("Selected physical device {identity}");
+        Ok(chosen)
     }
 
     fn try_create(
         instance: &Instance,
         handle: vk::PhysicalDevice,
-        surface: &surface::Handle,
-    ) -> Result<Option<(Self, surface::Config)>> {
-        Ok(
-            if extensions::device::supported_by(instance, handle)?
-                && features::supported_by(instance, handle)
-                && let Some(surface_config) = surface.get_config(handle)?
-            {
+        surface: Option<&surface::Handle>,
+    ) -> Result<core::result::Result<Candidate, Vec<Reason>>> {
+        let mut reasons = Vec::new();
+
+        let capabilities = match extensions::device::select(instance, handle, surface.is_some())? {
+            Err(missing) => {
+                reasons.push(Reason::MissingExtensions(missing));
+                None
+            }
+            Ok(extensions) => match features::select(instance, handle, &extensions) {
+                Err(missing) => {
+                    reasons.push(Reason::MissingFeatures(missing));
+                    None
+                }
+                Ok(features) => Some(Capabilities::new(extensions, features)),
+            },
+        };
+
+        let surface_config = match surface.map(|s| s.get_config(handle)).transpose()? {
+            Some(None) => {
+                reasons.push(Reason::NoSurfaceFormat);
+                None
+            }
+            surface_config => Some(surface_config.flatten()),
+        };
+
+        let queue_families = match queue::Families::find(instance, handle, surface)? {
+            Err(missing) => {
+                reasons.push(Reason::MissingQueueFamilies(missing));
+                None
+            }
+            Ok(queue_families) => Some(queue_families),
+        };
+
+        Ok(match (capabilities, surface_config, queue_families) {
+            (Some(capabilities), Some(surface_config), Some(queue_families)) => {
                 let physical_device = Self {
-                    properties: Properties::get_supported(instance, handle),
+                    properties: Properties::get_supported(
+                        instance,
+                        handle,
+                        capabilities.extensions(),
+                    ),
+                    capabilities,
+                    queue_families,
                     handle,
                 };
-                Some((physical_device, surface_config))
-            } else {
-                None
-            },
-        )
+                Ok((physical_device, surface_config))
+            }
+            _ => Err(reasons),
+        })
+    }
+
+    pub const fn properties(&self) -> &Properties {
+        &self.properties
+    }
+
+    pub const fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    pub const fn queue_families(&self) -> &queue::Families {
+        &self.queue_families
+    }
+}
+
+impl core::fmt::Display for Rejection {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let Self { name, reasons } = self;
+        write!(f, "{name}:")?;
+        for reason in reasons {
+            match reason {
+                Reason::MissingExtensions(extensions) => {
+                    write!(f, " missing extensions {extensions:?};")
+                }
+                Reason::MissingFeatures(features) => write!(f, " missing features {features:?};"),
+                Reason::NoSurfaceFormat => write!(f, " no supported surface format;"),
+                Reason::MissingQueueFamilies(families) => {
+                    write!(f, " missing queue families {families:?};")
+                }
+            }?;
+        }
+        Ok(())
+    }
+}
+
+impl core::fmt::Display for Rejections {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let Self(rejections) = self;
+        if rejections.is_empty() {
+            return write!(f, "no physical devices enumerated");
+        }
+        for rejection in rejections {
+            write!(f, "[{rejection}]")?;
+        }
+        Ok(())
     }
 }
 
@@ -56,14 +217,227 @@ impl core::fmt::Debug for PhysicalDevice {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let Self {
             properties,
+            capabilities,
+            queue_families,
             handle: _,
         } = self;
         f.debug_struct("PhysicalDevice")
             .field("properties", properties)
+            .field("capabilities", capabilities)
+            .field("queue_families", queue_families)
             .finish_non_exhaustive()
     }
 }
 
+mod selection {
+    use super::super::properties::{CoreProperties, DeviceType, Version};
+
+    mod conf {
+        pub const PREFERENCE_ENV_VAR: &str = "RAYGE_DEVICE";
+        pub const PREFERRED_DEVICE: Option<&str> = None;
+    }
+
+    pub struct Identity {
+        pub index: usize,
+        pub name: String,
+        pub vendor_id: u32,
+        pub device_id: u32,
+    }
+
+    // Ordered by priority, highest first
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    pub struct Score {
+        device_type: u8,
+        device_local_memory: u64,
+        api_version: Version,
+    }
+
+    #[derive(Debug, PartialEq, Eq)]
+    pub enum Preference {
+        Index(usize),
+        Id { vendor_id: u32, device_id: u32 },
+        Name(String),
+    }
+
+    impl Identity {
+        pub fn new(index: usize, properties: &CoreProperties) -> Self {
+            Self {
+                index,
+                name: properties.name.clone(),
+                vendor_id: properties.vendor_id,
+                device_id: properties.device_id,
+            }
+        }
+    }
+
+    impl Score {
+        pub fn new(properties: &CoreProperties) -> Self {
+            let device_type = match properties.device_type {
+                DeviceType::Discrete => 4,
+                DeviceType::Integrated => 3,
+                DeviceType::Virtual => 2,
+                DeviceType::Cpu => 1,
+                DeviceType::Other => 0,
+            };
+
+            Self {
+                device_type,
+                device_local_memory: properties.device_local_memory(),
+                api_version: properties.api_version,
+            }
+        }
+    }
+
+    impl Preference {
+        pub fn get() -> Option<Self> {
+            std::env::var(conf::PREFERENCE_ENV_VAR)
+                .ok()
+                .as_deref()
+                .or(conf::PREFERRED_DEVICE)
+                .map(Self::parse)
+        }
+
+        // `#<index>` or `index:<index>`, `<vendor id>:<device id>` in hex or else a device name
+        // substring, even if all digits
+        fn parse(preference: &str) -> Self {
+            if let Some(index) = preference
+                .strip_prefix('#')
+                .or_else(|| preference.strip_prefix("index:"))
+                && let Ok(index) = index.parse()
+            {
+                return Self::Index(index);
+            }
+
+            if let Some((vendor_id, device_id)) = preference.split_once(':')
+                && let Ok(vendor_id) = u32::from_str_radix(vendor_id.trim_start_matches("0x"), 16)
+                && let Ok(device_id) = u32::from_str_radix(device_id.trim_start_matches("0x"), 16)
+            {
+                return Self::Id {
+                    vendor_id,
+                    device_id,
+                };
+            }
+
+            Self::Name(preference.to_lowercase())
+        }
+
+        pub fn matches(&self, identity: &Identity) -> bool {
+            match self {
+                Self::Index(index) => identity.index == *index,
+                Self::Id {
+                    vendor_id,
+                    device_id,
+                } => identity.vendor_id == *vendor_id && identity.device_id == *device_id,
+                Self::Name(name) => identity.name.to_lowercase().contains(name),
+            }
+        }
+    }
+
+    impl core::fmt::Display for Identity {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            let Self {
+                index,
+                name,
+                vendor_id,
+                device_id,
+            } = self;
+            write!(f, "#{index} {name} [{vendor_id:04x}:{device_id:04x}]")
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use ash::vk;
+
+        use super::{
+            super::super::properties::{Limits, MemoryHeap},
+            *,
+        };
+
+        fn properties(device_type: DeviceType, memory: u64, api_version: u32) -> CoreProperties {
+            CoreProperties {
+                name: String::from("Test Device"),
+                vendor_id: 0x10de,
+                device_id: 0x2204,
+                device_type,
+                driver_version: 0,
+                api_version: Version::from(api_version),
+                limits: Limits::from(vk::PhysicalDeviceLimits::default()),
+                memory_heaps: vec![
+                    MemoryHeap {
+                        size: memory,
+                        device_local: true,
+                    },
+                    // host memory, which doesn't count
+                    MemoryHeap {
+                        size: 1 << 40,
+                        device_local: false,
+                    },
+                ],
+            }
+        }
+
+        #[test]
+        fn parses_index() {
+            assert_eq!(Preference::parse("#3"), Preference::Index(3));
+            assert_eq!(Preference::parse("index:3"), Preference::Index(3));
+        }
+
+        #[test]
+        fn parses_id() {
+            let id = Preference::Id {
+                vendor_id: 0x10de,
+                device_id: 0x2204,
+            };
+            assert_eq!(Preference::parse("10de:2204"), id);
+            assert_eq!(Preference::parse("0x10de:0x2204"), id);
+        }
+
+        #[test]
+        fn parses_anything_else_as_name() {
+            assert_eq!(
+                Preference::parse("3080"),
+                Preference::Name(String::from("3080"))
+            );
+            assert_eq!(
+                Preference::parse("foo:bar"),
+                Preference::Name(String::from("foo:bar"))
+            );
+        }
+
+        #[test]
+        fn matches_identity() {
+            let identity = Identity::new(3, &properties(DeviceType::Discrete, 0, 0));
+            assert!(Preference::parse("#3").matches(&identity));
+            assert!(Preference::parse("10de:2204").matches(&identity));
+            assert!(Preference::parse("test dev").matches(&identity));
+            assert!(!Preference::parse("#2").matches(&identity));
+            assert!(!Preference::parse("10de:2205").matches(&identity));
+        }
+
+        #[test]
+        fn scores_by_type_then_memory_then_version() {
+            let gib = 1 << 30;
+            let vulkan_1_3 = vk::make_api_version(0, 1, 3, 0);
+            let vulkan_1_4 = vk::make_api_version(0, 1, 4, 0);
+
+            let mut scores = [
+                Score::new(&properties(DeviceType::Other, 16 * gib, vulkan_1_4)),
+                Score::new(&properties(DeviceType::Cpu, 16 * gib, vulkan_1_4)),
+                Score::new(&properties(DeviceType::Integrated, 16 * gib, vulkan_1_4)),
+                Score::new(&properties(DeviceType::Discrete, 4 * gib, vulkan_1_4)),
+                Score::new(&properties(DeviceType::Discrete, 8 * gib, vulkan_1_3)),
+                Score::new(&properties(DeviceType::Virtual, 16 * gib, vulkan_1_4)),
+                Score::new(&properties(DeviceType::Discrete, 8 * gib, vulkan_1_4)),
+            ];
+            let expected = [6, 4, 3, 2, 5, 1, 0].map(|idx| scores[idx]);
+
+            scores.sort_by(|a, b| b.cmp(a));
+            assert_eq!(scores, expected);
+        }
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("failed to enumerate physical devices / {0}")]
@@ -72,6 +446,8 @@ pub enum Error {
     DeviceExtensions(#[from] extensions::device::Error),
     #[error("surface / {0}")]
     Surface(#[from] surface::Error),
-    #[error("no suitable physical device found")]
-    NoSuitableCandidate,
+    #[error("queue / {0}")]
+    Queue(#[from] queue::Error),
+    #[error("no suitable physical device found / {0}")]
+    NoSuitableCandidate(Rejections),
 }
@@ -1,15 +1,70 @@
-use ash::vk;
+use std::collections::HashSet;
+
+use ash::{khr, vk};
 
 use super::instance;
 
 #[derive(Debug)]
 pub struct Properties {
     pub core: CoreProperties,
+    pub descriptor_indexing: DescriptorIndexingProperties,
     pub acceleration_structure: AccelerationStructureProperties,
     pub ray_tracing_pipeline: RayTracingPipelineProperties,
 }
+#[derive(Clone, Debug)]
+pub struct CoreProperties {
+    pub name: String,
+    pub vendor_id: u32,
+    pub device_id: u32,
+    pub device_type: DeviceType,
+    pub driver_version: u32,
+    pub api_version: Version,
+    pub limits: Limits,
+    pub memory_heaps: Vec<MemoryHeap>,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeviceType {
+    Discrete,
+    Integrated,
+    Virtual,
+    Cpu,
+    Other,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    pub max_image_dimension_2d: u32,
+    pub max_image_array_layers: u32,
+    pub max_push_constants_size: u32,
+    pub max_sampler_anisotropy: f32,
+    pub timestamp_period: f32,
+    pub min_uniform_buffer_offset_alignment: u64,
+    pub min_storage_buffer_offset_alignment: u64,
+    pub optimal_buffer_copy_offset_alignment: u64,
+    pub optimal_buffer_copy_row_pitch_alignment: u64,
+    pub non_coherent_atom_size: u64,
+}
+#[derive(Clone, Copy, Debug)]
+pub struct MemoryHeap {
+    pub size: u64,
+    pub device_local: bool,
+}
+// Most descriptors of each type an update after bind set can hold, and a single shader stage
+// access, the lower of the two limits
 #[derive(Debug)]
-pub struct CoreProperties;
+pub struct DescriptorIndexingProperties {
+    pub sampled_images: u32,
+    pub storage_images: u32,
+    pub samplers: u32,
+    pub storage_buffers: u32,
+    // shared by all types but samplers, per stage
+    pub resources: u32,
+}
 #[derive(Debug)]
 pub struct AccelerationStructureProperties {
     pub min_scratch_offset_alignment: u32,
@@ -17,40 +72,197 @@ pub struct AccelerationStructureProperties {
 #[derive(Debug)]
 pub struct RayTracingPipelineProperties {
     pub shader_group: ShaderGroupProperties,
+    pub max_ray_recursion_depth: u32,
 }
 #[derive(Debug)]
 pub struct ShaderGroupProperties {
     pub base_alignment: u32,
     pub handle_alignment: u32,
     pub handle_size: u32,
+    pub max_stride: u32,
 }
 
 impl Properties {
+    // Ray tracing properties are left zeroed unless their extensions are in `extensions`
     pub fn get_supported(
         instance: &instance::Instance,
         physical_device: vk::PhysicalDevice,
+        extensions: &HashSet<&std::ffi::CStr>,
     ) -> Self {
         let mut ray_tracing_pipeline = vk::PhysicalDeviceRayTracingPipelinePropertiesKHR::default();
         let mut acceleration_structure =
             vk::PhysicalDeviceAccelerationStructurePropertiesKHR::default();
 
-        let mut core = vk::PhysicalDeviceProperties2::default()
-            .push_next(&mut ray_tracing_pipeline)
-            .push_next(&mut acceleration_structure);
+        let mut vulkan_1_2 = vk::PhysicalDeviceVulkan12Properties::default();
+
+        let mut core = vk::PhysicalDeviceProperties2::default().push_next(&mut vulkan_1_2);
+        if extensions.contains(khr::ray_tracing_pipeline::NAME) {
+            core = core.push_next(&mut ray_tracing_pipeline);
+        }
+        if extensions.contains(khr::acceleration_structure::NAME) {
+            core = core.push_next(&mut acceleration_structure);
+        }
 
         unsafe { instance.get_physical_device_properties2(physical_device, &mut core) };
+        let memory = unsafe { instance.get_physical_device_memory_properties(physical_device) };
 
         Self {
-            core: CoreProperties::from(core.properties),
+            core: CoreProperties::from((core.properties, memory)),
+            descriptor_indexing: DescriptorIndexingProperties::from(vulkan_1_2),
             acceleration_structure: AccelerationStructureProperties::from(acceleration_structure),
             ray_tracing_pipeline: RayTracingPipelineProperties::from(ray_tracing_pipeline),
         }
     }
 }
 
-impl From<vk::PhysicalDeviceProperties> for CoreProperties {
-    fn from(_p: vk::PhysicalDeviceProperties) -> Self {
-        Self
+impl CoreProperties {
+    #[must_use]
+    pub fn get(instance: &instance::Instance, physical_device: vk::PhysicalDevice) -> Self {
+        let core = unsafe { instance.get_physical_device_properties(physical_device) };
+        let memory = unsafe { instance.get_physical_device_memory_properties(physical_device) };
+        Self::from((core, memory))
+    }
+
+    #[must_use]
+    pub fn device_local_memory(&self) -> u64 {
+        self.memory_heaps
+            .iter()
+            .filter(|heap| heap.device_local)
+            .map(|heap| heap.size)
+            .sum()
+    }
+}
+
+impl
+    From<(
+        vk::PhysicalDeviceProperties,
+        vk::PhysicalDeviceMemoryProperties,
+    )> for CoreProperties
+{
+    fn from(
+        (p, m): (
+            vk::PhysicalDeviceProperties,
+            vk::PhysicalDeviceMemoryProperties,
+        ),
+    ) -> Self {
+        Self {
+            name: p.device_name_as_c_str().map_or_else(
+                |_| String::from("<unknown>"),
+                |name| name.to_string_lossy().into_owned(),
+            ),
+            vendor_id: p.vendor_id,
+            device_id: p.device_id,
+            device_type: DeviceType::from(p.device_type),
+            driver_version: p.driver_version,
+            api_version: Version::from(p.api_version),
+            limits: Limits::from(p.limits),
+            memory_heaps: m
+                .memory_heaps_as_slice()
+                .iter()
+                .copied()
+                .map(MemoryHeap::from)
+                .collect(),
+        }
+    }
+}
+
+impl From<vk::PhysicalDeviceType> for DeviceType {
+    fn from(t: vk::PhysicalDeviceType) -> Self {
+        match t {
+            vk::PhysicalDeviceType::DISCRETE_GPU => Self::Discrete,
+            vk::PhysicalDeviceType::INTEGRATED_GPU => Self::Integrated,
+            vk::PhysicalDeviceType::VIRTUAL_GPU => Self::Virtual,
+            vk::PhysicalDeviceType::CPU => Self::Cpu,
+            _ => Self::Other,
+        }
+    }
+}
+
+impl From<u32> for Version {
+    fn from(v: u32) -> Self {
+        Self {
+            major: vk::api_version_major(v),
+            minor: vk::api_version_minor(v),
+            patch: vk::api_version_patch(v),
+        }
+    }
+}
+
+impl From<vk::PhysicalDeviceLimits> for Limits {
+    fn from(l: vk::PhysicalDeviceLimits) -> Self {
+        Self {
+            max_image_dimension_2d: l.max_image_dimension2_d,
+            max_image_array_layers: l.max_image_array_layers,
+            max_push_constants_size: l.max_push_constants_size,
+            max_sampler_anisotropy: l.max_sampler_anisotropy,
+            timestamp_period: l.timestamp_period,
+            min_uniform_buffer_offset_alignment: l.min_uniform_buffer_offset_alignment,
+            min_storage_buffer_offset_alignment: l.min_storage_buffer_offset_alignment,
+            optimal_buffer_copy_offset_alignment: l.optimal_buffer_copy_offset_alignment,
+            optimal_buffer_copy_row_pitch_alignment: l.optimal_buffer_copy_row_pitch_alignment,
+            non_coherent_atom_size: l.non_coherent_atom_size,
+        }
+    }
+}
+
+impl From<vk::MemoryHeap> for MemoryHeap {
+    fn from(h: vk::MemoryHeap) -> Self {
+        Self {
+            size: h.size,
+            device_local: h.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL),
+        }
+    }
+}
+
+impl core::fmt::Display for CoreProperties {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let Self {
+            name,
+            vendor_id,
+            device_id,
+            device_type,
+            driver_version,
+            api_version,
+            limits: _,
+            memory_heaps: _,
+        } = self;
+        write!(
+            f,
+            "{name} [{vendor_id:04x}:{device_id:04x}] ({device_type:?}, Vulkan {api_version}, \
+             driver {driver_version:#x}, {} MiB device local memory)",
+            self.device_local_memory() / (1024 * 1024)
+        )
+    }
+}
+
+impl core::fmt::Display for Version {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let Self {
+            major,
+            minor,
+            patch,
+        } = self;
+        write!(f, "{major}.{minor}.{patch}")
+    }
+}
+
+impl From<vk::PhysicalDeviceVulkan12Properties<'_>> for DescriptorIndexingProperties {
+    fn from(p: vk::PhysicalDeviceVulkan12Properties) -> Self {
+        Self {
+            sampled_images: p
+                .max_descriptor_set_update_after_bind_sampled_images
+                .min(p.max_per_stage_descriptor_update_after_bind_sampled_images),
+            storage_images: p
+                .max_descriptor_set_update_after_bind_storage_images
+                .min(p.max_per_stage_descriptor_update_after_bind_storage_images),
+            samplers: p
+                .max_descriptor_set_update_after_bind_samplers
+                .min(p.max_per_stage_descriptor_update_after_bind_samplers),
+            storage_buffers: p
+                .max_descriptor_set_update_after_bind_storage_buffers
+                .min(p.max_per_stage_descriptor_update_after_bind_storage_buffers),
+            resources: p.max_per_stage_update_after_bind_resources,
+        }
     }
 }
 
@@ -68,6 +280,7 @@ impl From<vk::PhysicalDeviceRayTracingPipelinePropertiesKHR<'_>> for RayTracingP
     fn from(p: vk::PhysicalDeviceRayTracingPipelinePropertiesKHR) -> Self {
         Self {
             shader_group: From::from(p),
+            max_ray_recursion_depth: p.max_ray_recursion_depth,
         }
     }
 }
@@ -78,6 +291,7 @@ impl From<vk::PhysicalDeviceRayTracingPipelinePropertiesKHR<'_>> for ShaderGroup
             base_alignment: p.shader_group_base_alignment,
             handle_alignment: p.shader_group_handle_alignment,
             handle_size: p.shader_group_handle_size,
+            max_stride: p.max_shader_group_stride,
         }
     }
 }
@@ -2,7 +2,7 @@ use std::collections::HashSet;
 
 use ash::vk;
 
-use super::{instance::Instance, physical_device::PhysicalDevice, surface};
+use super::{instance::Instance, surface};
 
 type Result<T> = core::result::Result<T, Error>;
 
@@ -13,12 +13,19 @@ pub struct Queues {
     transfer: Queue,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Graphics,
+    Transfer,
+}
+
 pub struct Queue {
     family: u32,
     index: u32,
     handle: vk::Queue,
 }
 
+#[derive(Debug)]
 pub struct Families {
     graphics: u32,
     compute: u32,
@@ -45,14 +52,23 @@ impl Queues {
         }
     }
 
-    pub fn create_infos(
-        instance: &Instance,
-        physical_device: &PhysicalDevice,
-        surface: &surface::Surface,
-    ) -> Result<(Vec<vk::DeviceQueueCreateInfo<'static>>, Families)> {
-        let families = Families::create_infos(instance, physical_device, surface)?;
+    pub const fn graphics(&self) -> &Queue {
+        &self.graphics
+    }
+
+    pub const fn transfer(&self) -> &Queue {
+        &self.transfer
+    }
+
+    pub const fn get(&self, kind: Kind) -> &Queue {
+        match kind {
+            Kind::Graphics => &self.graphics,
+            Kind::Transfer => &self.transfer,
+        }
+    }
 
-        let create_infos = families
+    pub fn create_infos(families: &Families) -> Vec<vk::DeviceQueueCreateInfo<'static>> {
+        families
             .unique()
             .iter()
             .map(|&index| {
@@ -60,9 +76,7 @@ impl Queues {
                     .queue_family_index(index)
                     .queue_priorities(&[1.0_f32])
             })
-            .collect::<Vec<_>>();
-
-        Ok((create_infos, families))
+            .collect()
     }
 }
 
@@ -76,46 +90,54 @@ impl Queue {
             handle,
         }
     }
+
+    pub const fn family(&self) -> u32 {
+        self.family
+    }
+}
+
+impl std::ops::Deref for Queue {
+    type Target = vk::Queue;
+    fn deref(&self) -> &Self::Target {
+        &self.handle
+    }
 }
 
 impl Families {
-    pub fn create_infos(
+    pub fn find(
         instance: &Instance,
-        physical_device: &PhysicalDevice,
-        surface: &surface::Surface,
-    ) -> Result<Self> {
+        physical_device: vk::PhysicalDevice,
+        surface: Option<&surface::Handle>,
+    ) -> Result<core::result::Result<Self, Vec<&'static str>>> {
         let family_props =
-            unsafe { instance.get_physical_device_queue_family_properties(**physical_device) }
+            unsafe { instance.get_physical_device_queue_family_properties(physical_device) }
                 .into_iter()
                 .enumerate()
                 .filter(|(_, family)| family.queue_count > 0);
 
+        // Compute and transfer prefer families of their own, so their work can overlap with
+        // graphics, and fall back to sharing one when the device has none (e.g. lavapipe)
         let mut found_indices = FamiliesInfo::default();
+        let mut graphics_flags = vk::QueueFlags::empty();
         for (index, queue_family) in family_props {
             let idx = index as u32;
+            let flags = queue_family.queue_flags;
 
-            let g = queue_family.queue_flags.contains(vk::QueueFlags::GRAPHICS);
-            let c = queue_family.queue_flags.contains(vk::QueueFlags::COMPUTE);
-            let t = queue_family.queue_flags.contains(vk::QueueFlags::TRANSFER);
-            let vd = queue_family
-                .queue_flags
-                .contains(vk::QueueFlags::VIDEO_DECODE_KHR);
-            let ve = queue_family
-                .queue_flags
-                .contains(vk::QueueFlags::VIDEO_ENCODE_KHR);
+            let g = flags.contains(vk::QueueFlags::GRAPHICS);
+            let c = flags.contains(vk::QueueFlags::COMPUTE);
+            let t = flags.contains(vk::QueueFlags::TRANSFER);
+            let video = flags
+                .intersects(vk::QueueFlags::VIDEO_DECODE_KHR | vk::QueueFlags::VIDEO_ENCODE_KHR);
 
             if found_indices.graphics.is_none()
                 && g
-                && surface.is_supported_by(physical_device, idx)?
+                && surface.map_or(Ok(true), |s| s.is_supported_by(physical_device, idx))?
             {
                 found_indices.graphics = Some(idx);
-            } else if found_indices.compute.is_none() && c {
-                if let Some(g_idx) = found_indices.graphics
-                    && idx != g_idx
-                {
-                    found_indices.compute = Some(idx);
-                }
-            } else if found_indices.transfer.is_none() && t && !vd && !ve {
+                graphics_flags = flags;
+            } else if found_indices.compute.is_none() && c && !g {
+                found_indices.compute = Some(idx);
+            } else if found_indices.transfer.is_none() && t && !g && !c && !video {
                 found_indices.transfer = Some(idx);
             }
 
@@ -124,7 +146,15 @@ impl Families {
             }
         }
 
-        Self::try_from(found_indices).map_err(Error::Create)
+        // Graphics and compute families support transfers whether or not they report it
+        if found_indices.compute.is_none() && graphics_flags.contains(vk::QueueFlags::COMPUTE) {
+            found_indices.compute = found_indices.graphics;
+        }
+        if found_indices.transfer.is_none() {
+            found_indices.transfer = found_indices.compute.or(found_indices.graphics);
+        }
+
+        Ok(Self::try_from(found_indices))
     }
 
     fn unique(&self) -> HashSet<u32> {
@@ -139,13 +169,31 @@ impl FamiliesInfo {
 }
 
 impl TryFrom<FamiliesInfo> for Families {
-    type Error = String;
+    type Error = Vec<&'static str>;
     fn try_from(value: FamiliesInfo) -> core::result::Result<Self, Self::Error> {
-        Ok(Self {
-            graphics: value.graphics.ok_or("graphics")?,
-            compute: value.compute.ok_or("compute")?,
-            transfer: value.transfer.ok_or("compute")?,
-        })
+        match value {
+            FamiliesInfo {
+                graphics: Some(graphics),
+                compute: Some(compute),
+                transfer: Some(transfer),
+            } => Ok(Self {
+                graphics,
+                compute,
+                transfer,
+            }),
+            FamiliesInfo {
+                graphics,
+                compute,
+                transfer,
+            } => Err([
+                (graphics, "graphics"),
+                (compute, "compute"),
+                (transfer, "transfer"),
+            ]
+            .into_iter()
+            .filter_map(|(family, name)| family.is_none().then_some(name))
+            .collect()),
+        }
     }
 }
 
@@ -165,8 +213,6 @@ impl core::fmt::Debug for Queue {
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
-    #[error("failed to create {0} queue")]
-    Create(String),
     #[error("surface error / {0}")]
     Surface(#[from] surface::Error),
 }
@@ -0,0 +1,50 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard},
+};
+
+use ash::vk;
+
+const ENABLED: bool = cfg!(debug_assertions);
+
+// Live Vulkan objects by their debug names, only tracked in debug builds
+#[derive(Default)]
+pub struct Registry {
+    live: Mutex<HashMap<(vk::ObjectType, u64), String>>,
+}
+
+impl Registry {
+    pub fn insert<H: vk::Handle>(&self, object: H, name: &str) {
+        if ENABLED {
+            self.lock()
+                .insert((H::TYPE, object.as_raw()), name.to_owned());
+        }
+    }
+
+    pub fn remove<H: vk::Handle>(&self, object: H) {
+        if ENABLED {
+            self.lock().remove(&(H::TYPE, object.as_raw()));
+        }
+    }
+
+    pub fn report(&self) {
+        let mut leaked = self
+            .lock()
+            .iter()
+            .map(|(&object, name)| (object, name.clone()))
+            .collect::<Vec<_>>();
+        if leaked.is_empty() {
+            return;
+        }
+
+        leaked.sort_unstable();
+        for ((object_type, handle), name) in &leaked {
+            tracing::warn!("Leaked {object_type:?} {name:?} ({handle:#x})");
+        }
+        tracing::warn!("{} Vulkan objects were never destroyed", leaked.len());
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<(vk::ObjectType, u64), String>> {
+        self.live.lock().expect("Registry lock poisoned")
+    }
+}
@@ -1,17 +1,38 @@
 use ash::{khr, vk};
-use raw_window_handle::{HasWindowHandle, RawWindowHandle};
+use raw_window_handle::{HasDisplayHandle, HasWindowHandle, RawDisplayHandle, RawWindowHandle};
 
 use super::{instance::Instance, physical_device::PhysicalDevice};
 
 pub mod conf {
     use ash::vk;
 
-    pub const FORMAT: vk::SurfaceFormatKHR = vk::SurfaceFormatKHR {
-        format: vk::Format::B8G8R8A8_SRGB,
-        color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
-    };
-    pub const PREFERRED_PRESENT_MODE: vk::PresentModeKHR = vk::PresentModeKHR::FIFO_RELAXED;
-    pub const FALLBACK_PRESENT_MODE: vk::PresentModeKHR = vk::PresentModeKHR::FIFO;
+    use super::PresentMode;
+
+    // Most preferred first, anything else the surface offers comes after
+    pub const FORMATS: &[vk::SurfaceFormatKHR] = &[
+        // HDR10
+        vk::SurfaceFormatKHR {
+            format: vk::Format::A2B10G10R10_UNORM_PACK32,
+            color_space: vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+        },
+        // scRGB
+        vk::SurfaceFormatKHR {
+            format: vk::Format::R16G16B16A16_SFLOAT,
+            color_space: vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+        },
+        vk::SurfaceFormatKHR {
+            format: vk::Format::B8G8R8A8_SRGB,
+            color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+        },
+        vk::SurfaceFormatKHR {
+            format: vk::Format::R8G8B8A8_SRGB,
+            color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+        },
+    ];
+    // initial choices, switchable at runtime
+    pub const PREFERRED_PRESENT_MODE: PresentMode = PresentMode::FifoRelaxed;
+    // always supported
+    pub const FALLBACK_PRESENT_MODE: PresentMode = PresentMode::Fifo;
     pub const PREFERRED_IMAGE_COUNT: u32 = 3;
 }
 
@@ -24,14 +45,32 @@ pub struct Surface {
 
 #[derive(Debug)]
 pub struct Config {
-    pub present_mode: vk::PresentModeKHR,
+    pub format: vk::SurfaceFormatKHR,
+    pub present_mode: PresentMode,
+    // supported by the surface, in the order of `PresentMode::ALL`
+    pub present_modes: Vec<PresentMode>,
     pub extent: vk::Extent2D,
     pub image_count: u32,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PresentMode {
+    // vsync
+    Fifo,
+    // vsync, unless a frame is late, in which case it tears
+    FifoRelaxed,
+    // vsync, with late frames replaced by newer ones instead of queued
+    Mailbox,
+    // no vsync, tears
+    Immediate,
+}
+
 pub struct Handle {
     surface: vk::SurfaceKHR,
     loader: khr::surface::Instance,
+    // size of the window's drawable area, which the swapchain gets when the surface leaves it up
+    // to it, e.g. on Wayland
+    window_extent: vk::Extent2D,
 }
 
 impl Surface {
@@ -39,25 +78,52 @@ impl Surface {
         Self { config, handle }
     }
 
+    // Takes effect once the swapchain is recreated
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) -> Result<()> {
+        if !self.config.present_modes.contains(&present_mode) {
+            return Err(Error::UnsupportedPresentMode(present_mode));
+        }
+        self.config.present_mode = present_mode;
+        Ok(())
+    }
+
+    // Takes effect on the next `refresh_capabilities`
+    pub const fn set_window_extent(&mut self, window_extent: vk::Extent2D) {
+        self.handle.window_extent = window_extent;
+    }
+
     pub fn refresh_capabilities(&mut self, physical_device: &PhysicalDevice) -> Result<bool> {
-        Ok(self
-            .config
-            .update_with(&self.get_capabilities(**physical_device)?))
+        Ok(self.config.update_with(
+            &self.get_capabilities(**physical_device)?,
+            self.handle.window_extent,
+        ))
     }
 }
 
 impl Config {
-    fn update_with(&mut self, capabilities: &vk::SurfaceCapabilitiesKHR) -> bool {
-        self.extent = Handle::choose_extent(capabilities);
+    fn update_with(
+        &mut self,
+        capabilities: &vk::SurfaceCapabilitiesKHR,
+        window_extent: vk::Extent2D,
+    ) -> bool {
+        self.extent = Handle::choose_extent(capabilities, window_extent);
         self.extent.width != 0 && self.extent.height != 0
     }
 }
 
 impl Handle {
-    pub fn new(instance: &Instance, window: &impl HasWindowHandle) -> Result<Self> {
+    pub fn new(
+        instance: &Instance,
+        window: &(impl HasWindowHandle + HasDisplayHandle),
+        window_extent: vk::Extent2D,
+    ) -> Result<Self> {
         let surface = create_surface(instance, window)?;
         let loader = khr::surface::Instance::new(&instance.entry, instance);
-        Ok(Self { surface, loader })
+        Ok(Self {
+            surface,
+            loader,
+            window_extent,
+        })
     }
 
     pub fn get_config(&self, physical_device: vk::PhysicalDevice) -> Result<Option<Config>> {
@@ -74,18 +140,26 @@ impl Handle {
                 .get_physical_device_surface_present_modes(physical_device, self.surface)
                 .map_err(Error::GetConfigOptions)?
         };
+        let present_modes = PresentMode::ALL
+            .into_iter()
+            .filter(|&present_mode| present_modes.contains(&present_mode.into()))
+            .collect::<Vec<_>>();
 
-        Ok(Self::choose_best_surface_format(&surface_formats).map(|_| {
-            let extent = Self::choose_extent(&capabilities);
-            let image_count = Self::choose_image_count(&capabilities);
-            let present_mode = Self::choose_best_present_mode(&present_modes);
+        Ok(
+            Self::choose_best_surface_format(&surface_formats).map(|format| {
+                let extent = Self::choose_extent(&capabilities, self.window_extent);
+                let image_count = Self::choose_image_count(&capabilities);
+                let present_mode = Self::choose_best_present_mode(&present_modes);
 
-            Config {
-                present_mode,
-                extent,
-                image_count,
-            }
-        }))
+                Config {
+                    format,
+                    present_mode,
+                    present_modes,
+                    extent,
+                    image_count,
+                }
+            }),
+        )
     }
 
     fn get_capabilities(
@@ -102,10 +176,14 @@ impl Handle {
     fn choose_best_surface_format(
         formats: &[vk::SurfaceFormatKHR],
     ) -> Option<vk::SurfaceFormatKHR> {
-        formats.iter().copied().find(|f| *f == conf::FORMAT)
+        conf::FORMATS
+            .iter()
+            .find(|preferred| formats.contains(preferred))
+            .or_else(|| formats.first())
+            .copied()
     }
 
-    fn choose_best_present_mode(present_modes: &[vk::PresentModeKHR]) -> vk::PresentModeKHR {
+    fn choose_best_present_mode(present_modes: &[PresentMode]) -> PresentMode {
         if present_modes.contains(&conf::PREFERRED_PRESENT_MODE) {
             conf::PREFERRED_PRESENT_MODE
         } else {
@@ -113,17 +191,21 @@ impl Handle {
         }
     }
 
-    fn choose_extent(capabilities: &vk::SurfaceCapabilitiesKHR) -> vk::Extent2D {
+    // A current extent of `u32::MAX` means the surface takes the size of the swapchain
+    fn choose_extent(
+        capabilities: &vk::SurfaceCapabilitiesKHR,
+        window_extent: vk::Extent2D,
+    ) -> vk::Extent2D {
         if capabilities.current_extent.width != u32::MAX {
             return capabilities.current_extent;
         }
 
         vk::Extent2D {
-            width: capabilities.current_extent.width.clamp(
+            width: window_extent.width.clamp(
                 capabilities.min_image_extent.width,
                 capabilities.max_image_extent.width,
             ),
-            height: capabilities.current_extent.height.clamp(
+            height: window_extent.height.clamp(
                 capabilities.min_image_extent.height,
                 capabilities.max_image_extent.height,
             ),
@@ -146,13 +228,13 @@ impl Handle {
 
     pub fn is_supported_by(
         &self,
-        physical_device: &PhysicalDevice,
+        physical_device: vk::PhysicalDevice,
         queue_family_index: u32,
     ) -> Result<bool> {
         unsafe {
             self.loader
                 .get_physical_device_surface_support(
-                    **physical_device,
+                    physical_device,
                     queue_family_index,
                     self.surface,
                 )
@@ -161,9 +243,35 @@ impl Handle {
     }
 }
 
-fn create_surface(instance: &Instance, window: &impl HasWindowHandle) -> Result<vk::SurfaceKHR> {
-    match window.window_handle()?.as_raw() {
-        RawWindowHandle::Win32(handle) => {
+impl PresentMode {
+    pub const ALL: [Self; 4] = [
+        Self::Fifo,
+        Self::FifoRelaxed,
+        Self::Mailbox,
+        Self::Immediate,
+    ];
+}
+
+impl From<PresentMode> for vk::PresentModeKHR {
+    fn from(present_mode: PresentMode) -> Self {
+        match present_mode {
+            PresentMode::Fifo => Self::FIFO,
+            PresentMode::FifoRelaxed => Self::FIFO_RELAXED,
+            PresentMode::Mailbox => Self::MAILBOX,
+            PresentMode::Immediate => Self::IMMEDIATE,
+        }
+    }
+}
+
+fn create_surface(
+    instance: &Instance,
+    window: &(impl HasWindowHandle + HasDisplayHandle),
+) -> Result<vk::SurfaceKHR> {
+    match (
+        window.display_handle()?.as_raw(),
+        window.window_handle()?.as_raw(),
+    ) {
+        (RawDisplayHandle::Windows(_), RawWindowHandle::Win32(handle)) => {
             let create_info = vk::Win32SurfaceCreateInfoKHR::default()
                 .hwnd(handle.hwnd.get() as _)
                 .hinstance(handle.hinstance.expect("No Win32 HINSTANCE found").get() as _);
@@ -173,6 +281,40 @@ fn create_surface(instance: &Instance, window: &impl HasWindowHandle) -> Result<
                     .map_err(Error::Create)
             }
         }
+        // A missing display or connection means the window was made on the default one, which
+        // only the windowing library knows how to reach
+        (RawDisplayHandle::Xlib(display), RawWindowHandle::Xlib(handle)) => {
+            let display = display.display.ok_or(Error::MissingDisplayConnection)?;
+            let create_info = vk::XlibSurfaceCreateInfoKHR::default()
+                .dpy(display.as_ptr())
+                .window(handle.window);
+            unsafe {
+                khr::xlib_surface::Instance::new(&instance.entry, instance)
+                    .create_xlib_surface(&create_info, None)
+                    .map_err(Error::Create)
+            }
+        }
+        (RawDisplayHandle::Xcb(display), RawWindowHandle::Xcb(handle)) => {
+            let connection = display.connection.ok_or(Error::MissingDisplayConnection)?;
+            let create_info = vk::XcbSurfaceCreateInfoKHR::default()
+                .connection(connection.as_ptr())
+                .window(handle.window.get());
+            unsafe {
+                khr::xcb_surface::Instance::new(&instance.entry, instance)
+                    .create_xcb_surface(&create_info, None)
+                    .map_err(Error::Create)
+            }
+        }
+        (RawDisplayHandle::Wayland(display), RawWindowHandle::Wayland(handle)) => {
+            let create_info = vk::WaylandSurfaceCreateInfoKHR::default()
+                .display(display.display.as_ptr())
+                .surface(handle.surface.as_ptr());
+            unsafe {
+                khr::wayland_surface::Instance::new(&instance.entry, instance)
+                    .create_wayland_surface(&create_info, None)
+                    .map_err(Error::Create)
+            }
+        }
         _ => Err(Error::UnsupportedPlatform),
     }
 }
@@ -193,7 +335,11 @@ impl std::ops::Deref for Handle {
 
 impl Drop for Handle {
     fn drop(&mut self) {
-        let Self { surface, loader } = self;
+        let Self {
+            surface,
+            loader,
+            window_extent: _,
+        } = self;
         unsafe {
             loader.destroy_surface(*surface, None);
         }
@@ -211,12 +357,82 @@ impl core::fmt::Debug for Surface {
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
-    #[error("failed to get window handle / {0}")]
+    #[error("failed to get window / display handle / {0}")]
     GetHandle(#[from] raw_window_handle::HandleError),
     #[error("failed to create window surface / {0}")]
     Create(vk::Result),
     #[error("unsupported windowing platform")]
     UnsupportedPlatform,
+    #[error(
+        "window has no X11 display connection, create it on an explicitly opened display instead \
+         of the default one"
+    )]
+    MissingDisplayConnection,
     #[error("unable to get config options / {0}")]
     GetConfigOptions(vk::Result),
+    #[error("present mode {0:?} is not supported by the surface")]
+    UnsupportedPresentMode(PresentMode),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn format(format: vk::Format, color_space: vk::ColorSpaceKHR) -> vk::SurfaceFormatKHR {
+        vk::SurfaceFormatKHR {
+            format,
+            color_space,
+        }
+    }
+
+    const SRGB: vk::SurfaceFormatKHR =
+        format(vk::Format::B8G8R8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR);
+    const HDR10: vk::SurfaceFormatKHR = format(
+        vk::Format::A2B10G10R10_UNORM_PACK32,
+        vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+    );
+    const SCRGB: vk::SurfaceFormatKHR = format(
+        vk::Format::R16G16B16A16_SFLOAT,
+        vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+    );
+    const UNORM: vk::SurfaceFormatKHR = format(
+        vk::Format::B8G8R8A8_UNORM,
+        vk::ColorSpaceKHR::SRGB_NONLINEAR,
+    );
+
+    #[test]
+    fn prefers_hdr_formats() {
+        assert_eq!(
+            Handle::choose_best_surface_format(&[SRGB, SCRGB, HDR10]),
+            Some(HDR10)
+        );
+        assert_eq!(
+            Handle::choose_best_surface_format(&[UNORM, SRGB, SCRGB]),
+            Some(SCRGB)
+        );
+    }
+
+    #[test]
+    fn skips_hdr_formats_in_another_color_space() {
+        let hdr10_srgb = format(HDR10.format, vk::ColorSpaceKHR::SRGB_NONLINEAR);
+        assert_eq!(
+            Handle::choose_best_surface_format(&[hdr10_srgb, UNORM, SRGB]),
+            Some(SRGB)
+        );
+    }
+
+    #[test]
+    fn falls_back_to_the_first_offered_format() {
+        assert_eq!(
+            Handle::choose_best_surface_format(&[
+                UNORM,
+                format(
+                    vk::Format::R8G8B8A8_UNORM,
+                    vk::ColorSpaceKHR::SRGB_NONLINEAR
+                )
+            ]),
+            Some(UNORM)
+        );
+        assert_eq!(Handle::choose_best_surface_format(&[]), None);
+    }
 }
@@ -0,0 +1,124 @@
+use std::ffi::{CStr, c_void};
+
+use ash::{Entry, ext, vk};
+
+pub const ENABLED: bool = cfg!(feature = "validation");
+
+const LAYER: &CStr = c"VK_LAYER_KHRONOS_validation";
+
+type Result<T> = core::result::Result<T, Error>;
+
+pub struct Messenger {
+    handle: vk::DebugUtilsMessengerEXT,
+    loader: ext::debug_utils::Instance,
+}
+
+pub fn layer(entry: &Entry) -> Result<Option<&'static CStr>> {
+    if !ENABLED {
+        return Ok(None);
+    }
+
+    let available = unsafe {
+        entry
+            .enumerate_instance_layer_properties()
+            .map_err(Error::EnumerateLayers)?
+    };
+
+    let is_available = available
+        .iter()
+        .any(|layer| layer.layer_name_as_c_str() == Ok(LAYER));
+    if !is_available {
+        tracing::warn!("Validation requested but {LAYER:?} is not installed");
+    }
+
+    Ok(is_available.then_some(LAYER))
+}
+
+impl Messenger {
+    pub fn new(entry: &Entry, instance: &ash::Instance) -> Result<Self> {
+        let loader = ext::debug_utils::Instance::new(entry, instance);
+
+        let handle = unsafe {
+            loader
+                .create_debug_utils_messenger(&Self::create_info(), None)
+                .map_err(Error::Create)?
+        };
+
+        Ok(Self { handle, loader })
+    }
+
+    pub fn create_info() -> vk::DebugUtilsMessengerCreateInfoEXT<'static> {
+        vk::DebugUtilsMessengerCreateInfoEXT::default()
+            .message_severity(
+                vk::DebugUtilsMessageSeverityFlagsEXT::ERROR
+                    | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
+                    | vk::DebugUtilsMessageSeverityFlagsEXT::INFO
+                    | vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE,
+            )
+            .message_type(
+                vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
+                    | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
+                    | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
+            )
+            .pfn_user_callback(Some(callback))
+    }
+}
+
+impl Drop for Messenger {
+    fn drop(&mut self) {
+        let Self { handle, loader } = self;
+        unsafe {
+            loader.destroy_debug_utils_messenger(*handle, None);
+        }
+    }
+}
+
+unsafe extern "system" fn callback(
+    severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    kind: vk::DebugUtilsMessageTypeFlagsEXT,
+    data: *const vk::DebugUtilsMessengerCallbackDataEXT<'_>,
+    _user_data: *mut c_void,
+) -> vk::Bool32 {
+    let data = unsafe { &*data };
+
+    let id = unsafe { data.message_id_name_as_c_str() }
+        .map(CStr::to_string_lossy)
+        .unwrap_or_default();
+    let message = unsafe { data.message_as_c_str() }
+        .map(CStr::to_string_lossy)
+        .unwrap_or_default();
+    let objects = if data.object_count == 0 {
+        &[]
+    } else {
+        unsafe { std::slice::from_raw_parts(data.p_objects, data.object_count as usize) }
+    }
+    .iter()
+    .filter_map(|object| unsafe { object.object_name_as_c_str() })
+    .map(CStr::to_string_lossy)
+    .collect::<Vec<_>>()
+    .join(", ");
+
+    if severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR) {
+        tracing::error!(?kind, %id, %objects, "{message}");
+    } else if severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING) {
+        tracing::warn!(?kind, %id, %objects, "{message}");
+    } else if severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::INFO)
+        && !kind.contains(vk::DebugUtilsMessageTypeFlagsEXT::GENERAL)
+    {
+        tracing::info!(?kind, %id, %objects, "{message}");
+    } else if severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::INFO) {
+        tracing::debug!(?kind, %id, %objects, "{message}");
+    } else {
+        tracing::trace!(?kind, %id, %objects, "{message}");
+    }
+
+    vk::FALSE
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("failed to enumerate instance layers / {0}")]
+    EnumerateLayers(vk::Result),
+    #[error("failed to create debug messenger / {0}")]
+    Create(vk::Result),
+}
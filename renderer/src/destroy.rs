@@ -17,3 +17,69 @@ impl<C, T: Destroy<C>> Destroy<C> for Vec<T> {
         }
     }
 }
+
+impl<C, T: Destroy<C>> Destroy<C> for Option<T> {
+    fn destroy_with(&mut self, ctx: &C) {
+        if let Some(e) = self {
+            e.destroy_with(ctx);
+        }
+    }
+}
+
+// Resources destroyed only once the GPU is past the timeline value that last used them
+pub struct Queue<C> {
+    pending: Vec<Pending<C>>,
+}
+
+struct Pending<C> {
+    last_use: u64,
+    resource: Box<dyn Destroy<C>>,
+}
+
+impl<C> Queue<C> {
+    pub fn push(&mut self, last_use: u64, resource: impl Destroy<C> + 'static) {
+        self.pending.push(Pending {
+            last_use,
+            resource: Box::new(resource),
+        });
+    }
+
+    // Destroys everything last used at or before `completed`
+    pub fn collect(&mut self, ctx: &C, completed: u64) {
+        self.pending.retain_mut(|pending| {
+            let is_done = pending.last_use <= completed;
+            if is_done {
+                pending.resource.destroy_with(ctx);
+            }
+            !is_done
+        });
+    }
+}
+
+impl<C> Default for Queue<C> {
+    fn default() -> Self {
+        Self {
+            pending: Vec::new(),
+        }
+    }
+}
+
+// Expects the GPU to be idle
+impl<C> Destroy<C> for Queue<C> {
+    fn destroy_with(&mut self, ctx: &C) {
+        let Self { pending } = self;
+        for mut pending in pending.drain(..) {
+            pending.resource.destroy_with(ctx);
+        }
+    }
+}
+
+// For `Drop` impls of resources whose `destroy_with` resets their handle to null, debug builds only
+pub fn debug_assert_destroyed<H: ash::vk::Handle>(handle: H, resource: &str) {
+    if cfg!(debug_assertions) && !std::thread::panicking() {
+        assert!(
+            handle.as_raw() == 0,
+            "{resource} dropped without being destroyed"
+        );
+    }
+}
@@ -1,71 +1,390 @@
 #![feature(adt_const_params)]
-#![feature(array_try_from_fn)]
 #![feature(let_chains)]
 
-use context::device;
+use ash::vk;
+
+use base::{bindless, command, image};
+pub use context::properties::{CoreProperties, DeviceType, Limits, MemoryHeap, Version};
+pub use context::surface::PresentMode;
+use context::{device, surface};
 use destroy::Destroy;
+use offscreen::Offscreen;
+pub use path_tracer::Camera;
+use path_tracer::PathTracer;
+use resolve::Resolve;
+pub use resolve::ToneMapping;
+use scene::Scene;
 use swapchain::Swapchain;
 
 mod base;
 mod context;
 mod destroy;
+mod offscreen;
+mod path_tracer;
+mod resolve;
+mod scene;
+mod shaders;
 mod swapchain;
 
 pub type Result<T> = core::result::Result<T, Error>;
 
+mod conf {
+    pub const CLEAR_COLOR: [f32; 4] = [0.1, 0.1, 0.1, 1.0];
+}
+
 pub struct Renderer {
-    swapchain: swapchain::Swapchain,
+    target: Target,
+    mode: Mode,
+    // both `None` on devices without ray tracing, which only support `Mode::Clear`
+    scene: Option<Scene>,
+    path_tracer: Option<PathTracer>,
+    resolve: Resolve,
+    bindless: bindless::Heap,
     needs_resizing: bool,
     ctx: context::Context,
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mode {
+    // clears to a flat color, nothing is traced
+    Clear,
+    #[default]
+    PathTrace,
+}
+
+enum Target {
+    Swapchain(Swapchain),
+    Offscreen(Box<Offscreen>),
+}
+
 impl Renderer {
-    pub fn new(window: &impl raw_window_handle::HasWindowHandle) -> Result<Self> {
-        let ctx = context::Context::new(window)?;
-        let swapchain = swapchain::Swapchain::new(&ctx)?;
+    // `width` and `height` of the window's drawable area, in pixels
+    pub fn new(
+        window: &(impl raw_window_handle::HasWindowHandle + raw_window_handle::HasDisplayHandle),
+        width: u32,
+        height: u32,
+    ) -> Result<Self> {
+        let ctx = context::Context::new(window, vk::Extent2D { width, height })?;
+        let swapchain = Swapchain::new(&ctx)?;
+        Self::with_target(ctx, Target::Swapchain(swapchain))
+    }
 
-        Ok(Self {
-            swapchain,
-            needs_resizing: false,
-            ctx,
-        })
+    pub fn new_headless(width: u32, height: u32) -> Result<Self> {
+        let ctx = context::Context::new_headless()?;
+        let offscreen = Offscreen::new(&ctx, vk::Extent2D { width, height })?;
+        Self::with_target(ctx, Target::Offscreen(Box::new(offscreen)))
+    }
+
+    // Takes care of destroying `target` if anything else fails to be created
+    fn with_target(ctx: context::Context, mut target: Target) -> Result<Self> {
+        let mut bindless = None;
+        let mut scene = None;
+        let mut path_tracer = None;
+        let result = (|| {
+            let heap = bindless.insert(bindless::Heap::new(&ctx)?);
+            if ctx.capabilities().has_ray_tracing() {
+                let scene = scene.insert(Scene::new(&ctx)?);
+                path_tracer = Some(PathTracer::new(&ctx, heap, scene)?);
+            } else {
+                tracing::warn!("Device has no ray tracing support, path tracing is disabled");
+            }
+            Ok(Resolve::new(&ctx, heap, target.format())?)
+        })();
+
+        match result {
+            Ok(resolve) => Ok(Self {
+                target,
+                mode: if path_tracer.is_some() {
+                    Mode::default()
+                } else {
+                    Mode::Clear
+                },
+                scene,
+                path_tracer,
+                resolve,
+                bindless: bindless.expect("Created on success"),
+                needs_resizing: false,
+                ctx,
+            }),
+            Err(err) => {
+                path_tracer.destroy_with(&ctx);
+                scene.destroy_with(&ctx);
+                bindless.destroy_with(&ctx);
+                target.destroy_with(&ctx);
+                Err(err)
+            }
+        }
     }
 
     pub fn render(&mut self) -> Result<()> {
         if self.needs_resizing && !self.resize()? {
             return Ok(());
         }
+
+        let Self {
+            target,
+            mode,
+            scene,
+            path_tracer,
+            resolve,
+            bindless,
+            needs_resizing,
+            ctx,
+        } = self;
+
+        let completed = target.completed(ctx)?;
+        if *mode == Mode::PathTrace
+            && let Some(path_tracer) = path_tracer
+        {
+            let frame = target.submitted() + 1;
+            path_tracer.prepare(ctx, bindless, target.extent(), frame, completed)?;
+        }
+        bindless.flush(ctx, completed);
+
+        let mut frame = Frame {
+            path_tracing: scene
+                .as_ref()
+                .zip(path_tracer.as_mut())
+                .filter(|_| *mode == Mode::PathTrace),
+            resolve,
+            bindless,
+        };
+        match target {
+            Target::Swapchain(swapchain) => {
+                match swapchain.render(ctx, |recorder, image| frame.record(recorder, image)) {
+                    Err(swapchain::Error::NeedsRecreating) => *needs_resizing = true,
+                    result => result?,
+                }
+            }
+            Target::Offscreen(offscreen) => {
+                offscreen.render(ctx, |recorder, image| frame.record(recorder, image))?;
+            }
+        }
+        Ok(())
+    }
+
+    // `Mode::PathTrace` is ignored on devices without ray tracing
+    pub fn set_mode(&mut self, mode: Mode) {
+        if mode == Mode::PathTrace && self.path_tracer.is_none() {
+            tracing::warn!(
+                "Path tracing is unsupported on this device, keeping {:?}",
+                self.mode
+            );
+            return;
+        }
+        self.mode = mode;
+    }
+
+    #[must_use]
+    pub const fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    // Restarts accumulation if the camera moved
+    pub fn set_camera(&mut self, camera: Camera) {
+        if let Some(path_tracer) = &mut self.path_tracer {
+            path_tracer.set_camera(camera);
+        }
+    }
+
+    // Samples per pixel accumulated so far
+    #[must_use]
+    pub const fn samples(&self) -> u32 {
+        match &self.path_tracer {
+            Some(path_tracer) => path_tracer.samples(),
+            None => 0,
+        }
+    }
+
+    // Accumulation stops once `max_samples` samples per pixel are reached
+    pub const fn set_max_samples(&mut self, max_samples: u32) {
+        if let Some(path_tracer) = &mut self.path_tracer {
+            path_tracer.set_max_samples(max_samples);
+        }
+    }
+
+    // For changes the renderer can't see, such as to the scene
+    pub const fn reset_accumulation(&mut self) {
+        if let Some(path_tracer) = &mut self.path_tracer {
+            path_tracer.reset();
+        }
+    }
+
+    pub const fn set_tone_mapping(&mut self, tone_mapping: ToneMapping) {
+        self.resolve.set_tone_mapping(tone_mapping);
+    }
+
+    // In stops, 0 leaves the traced radiance unscaled
+    pub const fn set_exposure(&mut self, exposure: f32) {
+        self.resolve.set_exposure(exposure);
+    }
+
+    // Brightest the display gets in nits, where HDR outputs put tone mapped white
+    pub const fn set_peak_luminance(&mut self, nits: f32) {
+        self.resolve.set_peak_luminance(nits);
+    }
+
+    // Supported by the surface, empty when headless
+    #[must_use]
+    pub fn present_modes(&self) -> &[PresentMode] {
+        self.ctx
+            .surface
+            .as_ref()
+            .map_or(&[], |surface| &surface.config.present_modes)
+    }
+
+    #[must_use]
+    pub fn present_mode(&self) -> Option<PresentMode> {
+        self.ctx
+            .surface
+            .as_ref()
+            .map(|surface| surface.config.present_mode)
+    }
+
+    // Recreates the swapchain with `present_mode` before the next frame
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) -> Result<()> {
+        let surface = self.ctx.surface.as_mut().ok_or(Error::Headless)?;
+        if surface.config.present_mode != present_mode {
+            surface.set_present_mode(present_mode)?;
+            self.needs_resizing = true;
+        }
         Ok(())
     }
 
-    pub const fn needs_resizing(&mut self) {
+    // `width` and `height` of the window's drawable area, in pixels, which surfaces without an
+    // extent of their own such as Wayland ones take
+    pub const fn needs_resizing(&mut self, width: u32, height: u32) {
+        if let Some(surface) = &mut self.ctx.surface {
+            surface.set_window_extent(vk::Extent2D { width, height });
+        }
         self.needs_resizing = true;
     }
 
+    #[must_use]
+    pub const fn device_properties(&self) -> &CoreProperties {
+        &self.ctx.properties().core
+    }
+
+    pub fn read_back(&self) -> Result<Vec<u8>> {
+        match &self.target {
+            Target::Offscreen(offscreen) => Ok(offscreen.read_back(&self.ctx)?),
+            Target::Swapchain(_) => Err(Error::NotHeadless),
+        }
+    }
+
     fn resize(&mut self) -> Result<bool> {
+        let Target::Swapchain(swapchain) = &mut self.target else {
+            self.needs_resizing = false;
+            return Ok(true);
+        };
+
         let is_valid = self.ctx.refresh_surface_capabilities()?;
         if is_valid {
-            self.ctx.wait_idle()?;
-            self.swapchain.destroy_with(&self.ctx);
-            self.swapchain = Swapchain::new(&self.ctx)?;
+            swapchain.recreate(&self.ctx)?;
             self.needs_resizing = false;
         }
         Ok(is_valid)
     }
 }
 
+// Everything recorded into a frame besides the target
+struct Frame<'a> {
+    // `None` when clearing
+    path_tracing: Option<(&'a Scene, &'a mut PathTracer)>,
+    resolve: &'a Resolve,
+    bindless: &'a bindless::Heap,
+}
+
+impl Frame<'_> {
+    fn record<const F: image::Format>(
+        &mut self,
+        recorder: &command::Recorder,
+        image: &mut image::Image<F>,
+    ) {
+        if let Some((scene, path_tracer)) = &mut self.path_tracing {
+            path_tracer.record(recorder, self.bindless, scene);
+            // nothing to show before the first sample
+            if path_tracer.samples() > 0
+                && let Some(accumulation) = path_tracer.accumulation()
+            {
+                self.resolve.record(
+                    recorder,
+                    self.bindless,
+                    &mut accumulation.image,
+                    accumulation.id,
+                    image,
+                );
+                return;
+            }
+        }
+        image.transition(recorder, image::State::COLOR_ATTACHMENT);
+        image.clear(recorder, conf::CLEAR_COLOR);
+    }
+}
+
+impl Target {
+    fn completed(&self, ctx: &context::Context) -> Result<u64> {
+        Ok(match self {
+            Self::Swapchain(swapchain) => swapchain.completed(ctx)?,
+            Self::Offscreen(offscreen) => offscreen.completed(ctx)?,
+        })
+    }
+
+    const fn submitted(&self) -> u64 {
+        match self {
+            Self::Swapchain(swapchain) => swapchain.submitted(),
+            Self::Offscreen(offscreen) => offscreen.submitted(),
+        }
+    }
+
+    fn extent(&self) -> vk::Extent2D {
+        match self {
+            Self::Swapchain(swapchain) => swapchain.extent(),
+            Self::Offscreen(offscreen) => offscreen.extent(),
+        }
+    }
+
+    fn format(&self) -> vk::SurfaceFormatKHR {
+        match self {
+            Self::Swapchain(swapchain) => swapchain.format(),
+            Self::Offscreen(offscreen) => vk::SurfaceFormatKHR {
+                format: offscreen.format(),
+                color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+            },
+        }
+    }
+}
+
+impl Destroy<context::Context> for Target {
+    fn destroy_with(&mut self, ctx: &context::Context) {
+        match self {
+            Self::Swapchain(swapchain) => swapchain.destroy_with(ctx),
+            Self::Offscreen(offscreen) => offscreen.destroy_with(ctx),
+        }
+    }
+}
+
 impl Drop for Renderer {
     fn drop(&mut self) {
         use destroy::Destroy;
 
         let Self {
-            swapchain,
+            target,
+            mode: _,
+            scene,
+            path_tracer,
+            resolve,
+            bindless,
             needs_resizing: _,
             ctx,
         } = self;
 
         ctx.wait_idle().expect("Failed to wait for device to idle");
-        swapchain.destroy_with(ctx);
+        target.destroy_with(ctx);
+        resolve.destroy_with(ctx);
+        path_tracer.destroy_with(ctx);
+        scene.destroy_with(ctx);
+        bindless.destroy_with(ctx);
     }
 }
 
@@ -77,4 +396,20 @@ pub enum Error {
     Device(#[from] device::Error),
     #[error("swapchain / {0}")]
     Swapchain(#[from] swapchain::Error),
+    #[error("offscreen / {0}")]
+    Offscreen(#[from] offscreen::Error),
+    #[error("bindless / {0}")]
+    Bindless(#[from] bindless::Error),
+    #[error("scene / {0}")]
+    Scene(#[from] scene::Error),
+    #[error("path tracer / {0}")]
+    PathTracer(#[from] path_tracer::Error),
+    #[error("resolve / {0}")]
+    Resolve(#[from] resolve::Error),
+    #[error("surface / {0}")]
+    Surface(#[from] surface::Error),
+    #[error("renderer is not headless")]
+    NotHeadless,
+    #[error("renderer is headless")]
+    Headless,
 }
@@ -0,0 +1,153 @@
+use ash::vk;
+
+use crate::{
+    base::{
+        buffer::{self, Buffer},
+        command, image, semaphore,
+    },
+    context::{Context, queue},
+    destroy::Destroy,
+};
+
+type Result<T> = core::result::Result<T, Error>;
+
+pub struct Offscreen {
+    commands: command::Pools,
+    // reaches a frame's number once it has been rendered
+    timeline: semaphore::Timeline,
+    submitted: u64,
+    image: image::Image<{ image::Format::Rgba8Srgb }>,
+    // host visible copy of the image as of the last submitted frame
+    readback: Buffer,
+}
+
+impl Offscreen {
+    pub fn new(ctx: &Context, extent: vk::Extent2D) -> Result<Self> {
+        let mut commands = command::Pools::new(ctx, "offscreen:commands")?;
+        let mut timeline = None;
+        let mut image = None;
+        let result = (|| {
+            timeline = Some(semaphore::Timeline::new(ctx, 0, "offscreen:frames")?);
+            let image = image.insert(image::Image::create(
+                ctx,
+                image::Info::new(
+                    extent,
+                    vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+                ),
+                "offscreen",
+            )?);
+            Ok(Buffer::create(
+                ctx,
+                buffer::Kind::Staging,
+                image.texels_size()?,
+                "offscreen:readback",
+            )?)
+        })();
+
+        match result {
+            Ok(readback) => Ok(Self {
+                commands,
+                timeline: timeline.expect("Created on success"),
+                submitted: 0,
+                image: image.expect("Created on success"),
+                readback,
+            }),
+            Err(err) => {
+                image.destroy_with(ctx);
+                timeline.destroy_with(ctx);
+                commands.destroy_with(ctx);
+                Err(err)
+            }
+        }
+    }
+
+    // `record` draws the frame onto the target image, which it may leave in any state
+    pub fn render(
+        &mut self,
+        ctx: &Context,
+        record: impl FnOnce(&command::Recorder, &mut image::Image<{ image::Format::Rgba8Srgb }>),
+    ) -> Result<()> {
+        self.timeline.wait(ctx, self.submitted)?;
+        self.commands.reset(ctx)?;
+
+        let recorder = self.commands.begin(ctx, queue::Kind::Graphics)?;
+        recorder.begin_label("frame");
+        self.image.discard();
+        record(&recorder, &mut self.image);
+        let copied = self.image.copy_to_buffer(&recorder, &self.readback);
+        recorder.memory_barrier(
+            vk::MemoryBarrier2::default()
+                .src_stage_mask(vk::PipelineStageFlags2::COPY)
+                .src_access_mask(vk::AccessFlags2::TRANSFER_WRITE)
+                .dst_stage_mask(vk::PipelineStageFlags2::HOST)
+                .dst_access_mask(vk::AccessFlags2::HOST_READ),
+        );
+        recorder.end_label();
+        copied?;
+
+        let frame_number = self.submitted + 1;
+        command::submit(
+            ctx,
+            ctx.queues().graphics(),
+            &[recorder.finish()?],
+            &[],
+            &[self
+                .timeline
+                .submit_info(frame_number, vk::PipelineStageFlags2::ALL_COMMANDS)],
+            vk::Fence::null(),
+        )?;
+        self.submitted = frame_number;
+        Ok(())
+    }
+
+    // Timeline value of the most recent frame whose commands are done executing
+    pub fn completed(&self, ctx: &Context) -> Result<u64> {
+        Ok(self.timeline.value(ctx)?)
+    }
+
+    // Timeline value of the most recently submitted frame
+    pub const fn submitted(&self) -> u64 {
+        self.submitted
+    }
+
+    pub const fn extent(&self) -> vk::Extent2D {
+        self.image.extent()
+    }
+
+    pub const fn format(&self) -> vk::Format {
+        self.image.format()
+    }
+
+    pub fn read_back(&self, ctx: &Context) -> Result<Vec<u8>> {
+        self.timeline.wait(ctx, self.submitted)?;
+        Ok(self.readback.read(ctx)?)
+    }
+}
+
+impl Destroy<Context> for Offscreen {
+    fn destroy_with(&mut self, ctx: &Context) {
+        let Self {
+            commands,
+            timeline,
+            submitted: _,
+            image,
+            readback,
+        } = self;
+        commands.destroy_with(ctx);
+        timeline.destroy_with(ctx);
+        image.destroy_with(ctx);
+        readback.destroy_with(ctx);
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("buffer / {0}")]
+    Buffer(#[from] buffer::Error),
+    #[error("image / {0}")]
+    Image(#[from] image::Error),
+    #[error("semaphore / {0}")]
+    Semaphore(#[from] semaphore::Error),
+    #[error("command / {0}")]
+    Command(#[from] command::Error),
+}
@@ -0,0 +1,280 @@
+use ash::vk;
+
+use crate::{
+    base::{
+        bindless::{self, StorageImage},
+        command::Recorder,
+        image::{self, Image},
+        ray_tracing,
+    },
+    context::Context,
+    destroy::{self, Destroy},
+    scene::Scene,
+    shaders,
+};
+
+type Result<T> = core::result::Result<T, Error>;
+
+mod conf {
+    pub const MAX_BOUNCES: u32 = 8;
+    pub const MAX_SAMPLES: u32 = 4096;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    pub position: [f32; 3],
+    pub target: [f32; 3],
+    // radians
+    pub vertical_fov: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            position: [0.0, 2.0, 7.0],
+            target: [0.0, 0.75, 0.0],
+            vertical_fov: 45_f32.to_radians(),
+        }
+    }
+}
+
+// Traces one sample per pixel each frame and averages it into an HDR image, until the camera,
+// the extent or the scene changes
+pub struct PathTracer {
+    pipeline: ray_tracing::Pipeline,
+    sbt: ray_tracing::ShaderBindingTable,
+    accumulation: Option<Accumulation>,
+    camera: Camera,
+    samples: u32,
+    max_samples: u32,
+    // accumulation images replaced on resize, until every frame that may still use them is done
+    deferred: destroy::Queue<Context>,
+}
+
+pub struct Accumulation {
+    pub image: Image<{ image::Format::Rgba32Float }>,
+    pub id: bindless::Id<StorageImage>,
+}
+
+// Matches `PushConstants` in `path_trace.rgen`
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct PushConstants {
+    tlas: vk::DeviceAddress,
+    image: u32,
+    sample_index: u32,
+    max_bounces: u32,
+    origin: [f32; 3],
+    forward: [f32; 3],
+    right: [f32; 3],
+    up: [f32; 3],
+    padding: u32,
+}
+
+impl PathTracer {
+    pub fn new(ctx: &Context, heap: &bindless::Heap, scene: &Scene) -> Result<Self> {
+        let raygen = shaders::words(shaders::PATH_TRACE_RGEN);
+        let miss = shaders::words(shaders::PATH_TRACE_RMISS);
+        let closest_hit = shaders::words(shaders::PATH_TRACE_RCHIT);
+        let mut pipeline = ray_tracing::Builder::new(&raygen)
+            .miss(&miss)
+            .hit_group(ray_tracing::HitGroup::triangles(&closest_hit))
+            .max_recursion_depth(1)
+            .build(ctx, heap.pipeline_layout(), "path_tracer")?;
+
+        let sbt = scene
+            .hit_records()
+            .fold(
+                ray_tracing::TableBuilder::new(&pipeline),
+                |table, record| table.hit_record(0, record),
+            )
+            .build(ctx, "path_tracer:sbt");
+        let sbt = match sbt {
+            Ok(sbt) => sbt,
+            Err(err) => {
+                pipeline.destroy_with(ctx);
+                return Err(err.into());
+            }
+        };
+
+        Ok(Self {
+            pipeline,
+            sbt,
+            accumulation: None,
+            camera: Camera::default(),
+            samples: 0,
+            max_samples: conf::MAX_SAMPLES,
+            deferred: destroy::Queue::default(),
+        })
+    }
+
+    // Recreates the accumulation image if `extent` changed, retiring the old one once `frame`,
+    // the frame about to be recorded, is done. Meant to run before the heap is flushed.
+    pub fn prepare(
+        &mut self,
+        ctx: &Context,
+        heap: &mut bindless::Heap,
+        extent: vk::Extent2D,
+        frame: u64,
+        completed: u64,
+    ) -> Result<()> {
+        self.deferred.collect(ctx, completed);
+        if self
+            .accumulation
+            .as_ref()
+            .is_some_and(|accumulation| accumulation.image.extent() == extent)
+        {
+            return Ok(());
+        }
+
+        if let Some(Accumulation { image, id }) = self.accumulation.take() {
+            heap.remove(id, frame);
+            self.deferred.push(frame, image);
+        }
+        let mut image = Image::create(
+            ctx,
+            image::Info::new(extent, vk::ImageUsageFlags::STORAGE),
+            "path_tracer:accumulation",
+        )?;
+        let id = match heap.add_storage_image(image.view()) {
+            Ok(id) => id,
+            Err(err) => {
+                image.destroy_with(ctx);
+                return Err(err.into());
+            }
+        };
+        self.accumulation = Some(Accumulation { image, id });
+        self.samples = 0;
+        Ok(())
+    }
+
+    // Traces the next sample, if any are left, leaving the image for `accumulation` to read
+    pub fn record(&mut self, recorder: &Recorder, heap: &bindless::Heap, scene: &Scene) {
+        let Some(accumulation) = &mut self.accumulation else {
+            return;
+        };
+        if self.samples >= self.max_samples {
+            return;
+        }
+
+        let extent = accumulation.image.extent();
+        let (forward, right, up) = self.camera.basis(extent);
+        recorder.begin_label("path_trace");
+        accumulation
+            .image
+            .transition(recorder, image::State::RAY_TRACING_STORAGE);
+        self.pipeline.bind(recorder);
+        heap.bind(recorder, vk::PipelineBindPoint::RAY_TRACING_KHR);
+        recorder.push_constants(
+            self.pipeline.layout(),
+            &PushConstants {
+                tlas: scene.tlas_address(),
+                image: accumulation.id.index(),
+                sample_index: self.samples,
+                max_bounces: conf::MAX_BOUNCES,
+                origin: self.camera.position,
+                forward,
+                right,
+                up,
+                padding: 0,
+            },
+        );
+        self.sbt.trace(recorder, extent);
+        recorder.end_label();
+        self.samples += 1;
+    }
+
+    pub const fn accumulation(&mut self) -> Option<&mut Accumulation> {
+        self.accumulation.as_mut()
+    }
+
+    pub fn set_camera(&mut self, camera: Camera) {
+        if camera != self.camera {
+            self.camera = camera;
+            self.reset();
+        }
+    }
+
+    pub const fn reset(&mut self) {
+        self.samples = 0;
+    }
+
+    pub const fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    // Further samples are ignored until reset, previously accumulated ones are kept
+    pub const fn set_max_samples(&mut self, max_samples: u32) {
+        self.max_samples = max_samples;
+    }
+}
+
+impl Camera {
+    // Forward, then right and up scaled to the half extent of the view plane at distance 1
+    fn basis(&self, extent: vk::Extent2D) -> ([f32; 3], [f32; 3], [f32; 3]) {
+        let forward = normalize(sub(self.target, self.position));
+        let right = normalize(cross(forward, [0.0, 1.0, 0.0]));
+        let up = cross(right, forward);
+
+        let half_height = (self.vertical_fov / 2.0).tan();
+        let aspect = f64::from(extent.width) / f64::from(extent.height.max(1));
+        let half_width = half_height * aspect as f32;
+        (
+            forward,
+            right.map(|x| x * half_width),
+            up.map(|x| x * half_height),
+        )
+    }
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1].mul_add(b[2], -a[2] * b[1]),
+        a[2].mul_add(b[0], -a[0] * b[2]),
+        a[0].mul_add(b[1], -a[1] * b[0]),
+    ]
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let length = v[0].mul_add(v[0], v[1].mul_add(v[1], v[2] * v[2])).sqrt();
+    v.map(|x| x / length)
+}
+
+impl Destroy<Context> for Accumulation {
+    fn destroy_with(&mut self, ctx: &Context) {
+        let Self { image, id: _ } = self;
+        image.destroy_with(ctx);
+    }
+}
+
+impl Destroy<Context> for PathTracer {
+    fn destroy_with(&mut self, ctx: &Context) {
+        let Self {
+            pipeline,
+            sbt,
+            accumulation,
+            camera: _,
+            samples: _,
+            max_samples: _,
+            deferred,
+        } = self;
+        accumulation.destroy_with(ctx);
+        deferred.destroy_with(ctx);
+        sbt.destroy_with(ctx);
+        pipeline.destroy_with(ctx);
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("ray tracing / {0}")]
+    RayTracing(#[from] ray_tracing::Error),
+    #[error("image / {0}")]
+    Image(#[from] image::Error),
+    #[error("bindless / {0}")]
+    Bindless(#[from] bindless::Error),
+}
@@ -0,0 +1,374 @@
+use ash::vk;
+
+use crate::{
+    base::{
+        bindless::{self, StorageImage},
+        command::Recorder,
+        image::{self, Image},
+    },
+    context::{Context, device},
+    destroy::{self, Destroy},
+    shaders,
+};
+
+type Result<T> = core::result::Result<T, Error>;
+
+mod conf {
+    pub const ENTRY_POINT: &std::ffi::CStr = c"main";
+    // where HDR outputs put scene white, the BT.2408 reference white
+    pub const PAPER_WHITE_NITS: f32 = 203.0;
+    // displays can't be queried for it, so a common HDR1000 monitor is assumed
+    pub const DEFAULT_PEAK_NITS: f32 = 1000.0;
+}
+
+// Display transform of an HDR storage image onto a color target, drawn with a fullscreen triangle
+pub struct Resolve {
+    tone_mapping: ToneMapping,
+    // in stops, applied before tone mapping
+    exposure: f32,
+    // brightest output of HDR encodings, tone mapped 1.0, ignored by SDR ones
+    peak_nits: f32,
+    encoding: Encoding,
+    layout: vk::PipelineLayout,
+    handle: vk::Pipeline,
+}
+
+// Values match the constants in `resolve.frag`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ToneMapping {
+    Clamp = 0,
+    Reinhard = 1,
+    #[default]
+    AcesFitted = 2,
+    AgX = 3,
+}
+
+// What the shader does to its linear output before writing it, values match `resolve.frag`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Encoding {
+    // left as is, for targets that are linear or encode on write
+    Linear = 0,
+    Srgb = 1,
+    // HDR10, BT.2020 primaries with 1.0 at 10000 nits
+    Pq = 2,
+    // extended linear sRGB with 1.0 at 80 nits
+    ScRgb = 3,
+}
+
+// Matches `PushConstants` in `resolve.frag`
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct PushConstants {
+    image: u32,
+    tone_mapping: u32,
+    encoding: u32,
+    exposure: f32,
+    white_level: f32,
+}
+
+impl Resolve {
+    pub fn new(
+        ctx: &Context,
+        heap: &bindless::Heap,
+        target_format: vk::SurfaceFormatKHR,
+    ) -> Result<Self> {
+        let layout = heap.pipeline_layout();
+        let vertex = shaders::words(shaders::FULLSCREEN_VERT);
+        let fragment = shaders::words(shaders::RESOLVE_FRAG);
+
+        let mut modules = Vec::new();
+        let result = (|| {
+            for code in [&vertex, &fragment] {
+                let create_info = vk::ShaderModuleCreateInfo::default().code(code);
+                modules.push(unsafe {
+                    ctx.create_shader_module(&create_info, None)
+                        .map_err(Error::CreateShaderModule)?
+                });
+            }
+            Self::create(ctx, layout, modules[0], modules[1], target_format.format)
+        })();
+        for module in modules {
+            unsafe {
+                ctx.destroy_shader_module(module, None);
+            }
+        }
+
+        let mut resolve = Self {
+            tone_mapping: ToneMapping::default(),
+            exposure: 0.0,
+            peak_nits: conf::DEFAULT_PEAK_NITS,
+            encoding: Encoding::of(target_format),
+            layout,
+            handle: result?,
+        };
+        if let Err(err) = ctx.set_debug_name(resolve.handle, "resolve") {
+            resolve.destroy_with(ctx);
+            return Err(err.into());
+        }
+        Ok(resolve)
+    }
+
+    fn create(
+        ctx: &Context,
+        layout: vk::PipelineLayout,
+        vertex: vk::ShaderModule,
+        fragment: vk::ShaderModule,
+        target_format: vk::Format,
+    ) -> Result<vk::Pipeline> {
+        let stages = [
+            vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::VERTEX)
+                .module(vertex)
+                .name(conf::ENTRY_POINT),
+            vk::PipelineShaderStageCreateInfo::default()
+                .stage(vk::ShaderStageFlags::FRAGMENT)
+                .module(fragment)
+                .name(conf::ENTRY_POINT),
+        ];
+        let vertex_input = vk::PipelineVertexInputStateCreateInfo::default();
+        let input_assembly = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+        let viewport = vk::PipelineViewportStateCreateInfo::default()
+            .viewport_count(1)
+            .scissor_count(1);
+        let rasterization = vk::PipelineRasterizationStateCreateInfo::default()
+            .polygon_mode(vk::PolygonMode::FILL)
+            .cull_mode(vk::CullModeFlags::NONE)
+            .line_width(1.0);
+        let multisample = vk::PipelineMultisampleStateCreateInfo::default()
+            .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+        let blend_attachments = [vk::PipelineColorBlendAttachmentState::default()
+            .color_write_mask(vk::ColorComponentFlags::RGBA)];
+        let blend =
+            vk::PipelineColorBlendStateCreateInfo::default().attachments(&blend_attachments);
+        let dynamic = vk::PipelineDynamicStateCreateInfo::default()
+            .dynamic_states(&[vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR]);
+        let color_formats = [target_format];
+        let mut rendering =
+            vk::PipelineRenderingCreateInfo::default().color_attachment_formats(&color_formats);
+
+        let create_info = vk::GraphicsPipelineCreateInfo::default()
+            .stages(&stages)
+            .vertex_input_state(&vertex_input)
+            .input_assembly_state(&input_assembly)
+            .viewport_state(&viewport)
+            .rasterization_state(&rasterization)
+            .multisample_state(&multisample)
+            .color_blend_state(&blend)
+            .dynamic_state(&dynamic)
+            .layout(layout)
+            .push_next(&mut rendering);
+
+        unsafe {
+            ctx.create_graphics_pipelines(vk::PipelineCache::null(), &[create_info], None)
+                .map_err(|(_, err)| Error::Create(err))
+                .map(|pipelines| pipelines[0])
+        }
+    }
+
+    pub const fn set_tone_mapping(&mut self, tone_mapping: ToneMapping) {
+        self.tone_mapping = tone_mapping;
+    }
+
+    pub const fn set_exposure(&mut self, exposure: f32) {
+        self.exposure = exposure;
+    }
+
+    // Clamped to paper white, below which HDR would be dimmer than SDR
+    pub const fn set_peak_luminance(&mut self, nits: f32) {
+        self.peak_nits = nits.max(conf::PAPER_WHITE_NITS);
+    }
+
+    // Overwrites all of `target`
+    pub fn record<const F: image::Format>(
+        &self,
+        recorder: &Recorder,
+        heap: &bindless::Heap,
+        source: &mut Image<{ image::Format::Rgba32Float }>,
+        source_id: bindless::Id<StorageImage>,
+        target: &mut Image<F>,
+    ) {
+        recorder.begin_label("resolve");
+        source.transition(recorder, image::State::FRAGMENT_STORAGE_READ);
+        target.transition(recorder, image::State::COLOR_ATTACHMENT);
+
+        let extent = target.extent();
+        let color_attachments = [vk::RenderingAttachmentInfo::default()
+            .image_view(target.view())
+            .image_layout(image::State::COLOR_ATTACHMENT.layout)
+            .load_op(vk::AttachmentLoadOp::DONT_CARE)
+            .store_op(vk::AttachmentStoreOp::STORE)];
+        let rendering_info = vk::RenderingInfo::default()
+            .render_area(vk::Rect2D::default().extent(extent))
+            .layer_count(1)
+            .color_attachments(&color_attachments);
+
+        let ctx = recorder.ctx();
+        unsafe {
+            ctx.cmd_begin_rendering(**recorder, &rendering_info);
+            ctx.cmd_bind_pipeline(**recorder, vk::PipelineBindPoint::GRAPHICS, self.handle);
+            ctx.cmd_set_viewport(
+                **recorder,
+                0,
+                &[vk::Viewport::default()
+                    .width(f64::from(extent.width) as f32)
+                    .height(f64::from(extent.height) as f32)
+                    .max_depth(1.0)],
+            );
+            ctx.cmd_set_scissor(**recorder, 0, &[vk::Rect2D::default().extent(extent)]);
+        }
+        heap.bind(recorder, vk::PipelineBindPoint::GRAPHICS);
+        recorder.push_constants(
+            self.layout,
+            &PushConstants {
+                image: source_id.index(),
+                tone_mapping: self.tone_mapping as u32,
+                encoding: self.encoding as u32,
+                exposure: self.exposure + self.encoding.headroom(self.peak_nits),
+                white_level: self.encoding.white_level(self.peak_nits),
+            },
+        );
+        unsafe {
+            ctx.cmd_draw(**recorder, 3, 1, 0, 0);
+            ctx.cmd_end_rendering(**recorder);
+        }
+        recorder.end_label();
+    }
+}
+
+impl Encoding {
+    // Unknown color spaces are treated as sRGB, where only `*_SRGB` formats encode on write and
+    // everything else, float formats included, stores the shader's output as is
+    const fn of(format: vk::SurfaceFormatKHR) -> Self {
+        match (format.color_space, format.format) {
+            (vk::ColorSpaceKHR::HDR10_ST2084_EXT, _) => Self::Pq,
+            (vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT, _) => Self::ScRgb,
+            (
+                _,
+                vk::Format::R8G8B8A8_SRGB
+                | vk::Format::B8G8R8A8_SRGB
+                | vk::Format::A8B8G8R8_SRGB_PACK32,
+            ) => Self::Linear,
+            _ => Self::Srgb,
+        }
+    }
+
+    const fn is_hdr(self) -> bool {
+        matches!(self, Self::Pq | Self::ScRgb)
+    }
+
+    // Output units of tone mapped 1.0, the display's peak for HDR encodings
+    const fn white_level(self, peak_nits: f32) -> f32 {
+        match self {
+            Self::Linear | Self::Srgb => 1.0,
+            Self::Pq => peak_nits / 10000.0,
+            Self::ScRgb => peak_nits / 80.0,
+        }
+    }
+
+    // Stops between paper white and the peak, taken off the exposure of HDR encodings so scene
+    // white lands around paper white and only highlights reach the peak
+    fn headroom(self, peak_nits: f32) -> f32 {
+        if self.is_hdr() {
+            (conf::PAPER_WHITE_NITS / peak_nits).log2()
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Destroy<Context> for Resolve {
+    fn destroy_with(&mut self, ctx: &Context) {
+        let Self {
+            tone_mapping: _,
+            exposure: _,
+            peak_nits: _,
+            encoding: _,
+            layout: _,
+            handle,
+        } = self;
+        ctx.untrack(*handle);
+        unsafe {
+            ctx.destroy_pipeline(*handle, None);
+        }
+        *handle = vk::Pipeline::null();
+    }
+}
+
+impl Drop for Resolve {
+    fn drop(&mut self) {
+        destroy::debug_assert_destroyed(self.handle, "resolve pipeline");
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("failed to create shader module / {0}")]
+    CreateShaderModule(vk::Result),
+    #[error("failed to create graphics pipeline / {0}")]
+    Create(vk::Result),
+    #[error("device / {0}")]
+    Device(#[from] device::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn of(format: vk::Format, color_space: vk::ColorSpaceKHR) -> Encoding {
+        Encoding::of(vk::SurfaceFormatKHR {
+            format,
+            color_space,
+        })
+    }
+
+    #[test]
+    fn leaves_srgb_formats_to_encode_on_write() {
+        for format in [
+            vk::Format::R8G8B8A8_SRGB,
+            vk::Format::B8G8R8A8_SRGB,
+            vk::Format::A8B8G8R8_SRGB_PACK32,
+        ] {
+            assert_eq!(
+                of(format, vk::ColorSpaceKHR::SRGB_NONLINEAR),
+                Encoding::Linear
+            );
+        }
+    }
+
+    #[test]
+    fn encodes_srgb_into_unorm_formats() {
+        assert_eq!(
+            of(
+                vk::Format::B8G8R8A8_UNORM,
+                vk::ColorSpaceKHR::SRGB_NONLINEAR
+            ),
+            Encoding::Srgb
+        );
+        assert_eq!(
+            of(
+                vk::Format::A2B10G10R10_UNORM_PACK32,
+                vk::ColorSpaceKHR::SRGB_NONLINEAR
+            ),
+            Encoding::Srgb
+        );
+    }
+
+    #[test]
+    fn picks_hdr_encodings_by_color_space() {
+        assert_eq!(
+            of(
+                vk::Format::A2B10G10R10_UNORM_PACK32,
+                vk::ColorSpaceKHR::HDR10_ST2084_EXT
+            ),
+            Encoding::Pq
+        );
+        assert_eq!(
+            of(
+                vk::Format::R16G16B16A16_SFLOAT,
+                vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT
+            ),
+            Encoding::ScRgb
+        );
+    }
+}
@@ -0,0 +1,288 @@
+use ash::vk;
+
+use crate::{
+    base::{
+        acceleration::{self, AccelerationStructure, Tlas},
+        buffer::{self, Buffer},
+        command,
+    },
+    context::{Context, queue},
+    destroy::Destroy,
+};
+
+type Result<T> = core::result::Result<T, Error>;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Material {
+    pub albedo: [f32; 3],
+    pub emission: [f32; 3],
+}
+
+impl Material {
+    const fn diffuse(albedo: [f32; 3]) -> Self {
+        Self {
+            albedo,
+            emission: [0.0; 3],
+        }
+    }
+
+    const fn emissive(emission: [f32; 3]) -> Self {
+        Self {
+            albedo: [0.0; 3],
+            emission,
+        }
+    }
+}
+
+struct Mesh {
+    positions: Buffer,
+    indices: Buffer,
+    triangle_count: u32,
+}
+
+// A fixed test scene of a ground plane and a few boxes, lit by the sky and one emissive box
+pub struct Scene {
+    meshes: Vec<Mesh>,
+    blases: Vec<AccelerationStructure>,
+    tlas: Tlas,
+    // inline data of each instance's hit record, laid out as `Record` in `path_trace.rchit`
+    records: Vec<Vec<u8>>,
+}
+
+impl Scene {
+    pub fn new(ctx: &Context) -> Result<Self> {
+        let mut meshes = Vec::new();
+        let mut blases = Vec::new();
+        let result = (|| {
+            meshes.push(Mesh::upload(ctx, &QUAD_POSITIONS, &QUAD_INDICES, "quad")?);
+            meshes.push(Mesh::upload(ctx, &CUBE_POSITIONS, &CUBE_INDICES, "cube")?);
+
+            let geometries = meshes
+                .iter()
+                .map(|mesh| Ok([mesh.geometry()?]))
+                .collect::<Result<Vec<_>>>()?;
+            let infos = geometries
+                .iter()
+                .zip(["quad", "cube"])
+                .map(|(geometries, name)| acceleration::BlasInfo { geometries, name })
+                .collect::<Vec<_>>();
+            blases = acceleration::build_blases(ctx, &infos, true)?;
+
+            let (instances, records) = Self::layout(&meshes, &blases)?;
+            let mut tlas = Tlas::new(ctx, instances.len() as u32, 1, "scene")?;
+            let mut recorded = Ok(());
+            let submitted =
+                command::submit_once(ctx, queue::Kind::Graphics, "scene:tlas", |recorder| {
+                    recorded = tlas.record(recorder, 0, &instances, false);
+                })
+                .map_err(Error::from);
+            if let Err(err) = submitted.and_then(|()| Ok(recorded?)) {
+                tlas.destroy_with(ctx);
+                return Err(err);
+            }
+            Ok((tlas, records))
+        })();
+
+        match result {
+            Ok((tlas, records)) => Ok(Self {
+                meshes,
+                blases,
+                tlas,
+                records,
+            }),
+            Err(err) => {
+                blases.destroy_with(ctx);
+                meshes.destroy_with(ctx);
+                Err(err)
+            }
+        }
+    }
+
+    // (scale, translation, mesh, material) of every instance, with matching hit records
+    fn layout(
+        meshes: &[Mesh],
+        blases: &[AccelerationStructure],
+    ) -> Result<(Vec<acceleration::Instance>, Vec<Vec<u8>>)> {
+        let objects = [
+            (
+                [10.0, 1.0, 10.0],
+                [0.0, 0.0, 0.0],
+                0,
+                Material::diffuse([0.7, 0.7, 0.7]),
+            ),
+            (
+                [1.0, 1.0, 1.0],
+                [-1.5, 0.5, 0.0],
+                1,
+                Material::diffuse([0.8, 0.2, 0.2]),
+            ),
+            (
+                [1.0, 2.0, 1.0],
+                [0.0, 1.0, -1.0],
+                1,
+                Material::diffuse([0.2, 0.8, 0.2]),
+            ),
+            (
+                [1.0, 1.0, 1.0],
+                [1.5, 0.5, 0.5],
+                1,
+                Material::diffuse([0.2, 0.3, 0.9]),
+            ),
+            (
+                [0.5, 0.5, 0.5],
+                [0.0, 3.0, 1.0],
+                1,
+                Material::emissive([8.0, 7.0, 6.0]),
+            ),
+        ];
+
+        objects
+            .into_iter()
+            .zip(0..)
+            .map(|((scale, translation, mesh, material), index)| {
+                let instance = acceleration::Instance {
+                    transform: [
+                        [scale[0], 0.0, 0.0, translation[0]],
+                        [0.0, scale[1], 0.0, translation[1]],
+                        [0.0, 0.0, scale[2], translation[2]],
+                    ],
+                    custom_index: index,
+                    sbt_offset: index,
+                    ..acceleration::Instance::new(&blases[mesh])
+                };
+                Ok((instance, meshes[mesh].record(material)?))
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(|objects| objects.into_iter().unzip())
+    }
+
+    pub const fn tlas_address(&self) -> vk::DeviceAddress {
+        self.tlas.address()
+    }
+
+    // Inline data of the hit record for every instance, in instance order
+    pub fn hit_records(&self) -> impl Iterator<Item = &[u8]> {
+        self.records.iter().map(Vec::as_slice)
+    }
+}
+
+impl Mesh {
+    fn upload(ctx: &Context, positions: &[[f32; 3]], indices: &[u32], name: &str) -> Result<Self> {
+        let mut positions = Buffer::upload(
+            ctx,
+            buffer::Kind::Vertex,
+            positions,
+            &format!("{name}:positions"),
+        )?;
+        match Buffer::upload(
+            ctx,
+            buffer::Kind::Index,
+            indices,
+            &format!("{name}:indices"),
+        ) {
+            Ok(index_buffer) => Ok(Self {
+                positions,
+                indices: index_buffer,
+                triangle_count: indices.len() as u32 / 3,
+            }),
+            Err(err) => {
+                positions.destroy_with(ctx);
+                Err(err.into())
+            }
+        }
+    }
+
+    fn geometry(&self) -> Result<acceleration::Geometry> {
+        Ok(acceleration::Geometry::Triangles {
+            vertices: self.positions.address().ok_or(Error::NoAddress)?,
+            vertex_format: vk::Format::R32G32B32_SFLOAT,
+            vertex_stride: size_of::<[f32; 3]>() as vk::DeviceSize,
+            vertex_count: (self.positions.size() / size_of::<[f32; 3]>() as vk::DeviceSize) as u32,
+            indices: Some(self.indices.address().ok_or(Error::NoAddress)?),
+            triangle_count: self.triangle_count,
+            opaque: true,
+        })
+    }
+
+    fn record(&self, material: Material) -> Result<Vec<u8>> {
+        let positions = self.positions.address().ok_or(Error::NoAddress)?;
+        let indices = self.indices.address().ok_or(Error::NoAddress)?;
+        Ok([positions.to_ne_bytes(), indices.to_ne_bytes()]
+            .into_iter()
+            .flatten()
+            .chain(
+                material
+                    .albedo
+                    .into_iter()
+                    .chain(material.emission)
+                    .flat_map(f32::to_ne_bytes),
+            )
+            .collect())
+    }
+}
+
+const QUAD_POSITIONS: [[f32; 3]; 4] = [
+    [-1.0, 0.0, -1.0],
+    [1.0, 0.0, -1.0],
+    [1.0, 0.0, 1.0],
+    [-1.0, 0.0, 1.0],
+];
+const QUAD_INDICES: [u32; 6] = [0, 1, 2, 0, 2, 3];
+
+const CUBE_POSITIONS: [[f32; 3]; 8] = [
+    [-0.5, -0.5, -0.5],
+    [0.5, -0.5, -0.5],
+    [0.5, 0.5, -0.5],
+    [-0.5, 0.5, -0.5],
+    [-0.5, -0.5, 0.5],
+    [0.5, -0.5, 0.5],
+    [0.5, 0.5, 0.5],
+    [-0.5, 0.5, 0.5],
+];
+#[rustfmt::skip]
+const CUBE_INDICES: [u32; 36] = [
+    0, 2, 1, 0, 3, 2, // back
+    4, 5, 6, 4, 6, 7, // front
+    0, 1, 5, 0, 5, 4, // bottom
+    3, 6, 2, 3, 7, 6, // top
+    0, 4, 7, 0, 7, 3, // left
+    1, 2, 6, 1, 6, 5, // right
+];
+
+impl Destroy<Context> for Mesh {
+    fn destroy_with(&mut self, ctx: &Context) {
+        let Self {
+            positions,
+            indices,
+            triangle_count: _,
+        } = self;
+        positions.destroy_with(ctx);
+        indices.destroy_with(ctx);
+    }
+}
+
+impl Destroy<Context> for Scene {
+    fn destroy_with(&mut self, ctx: &Context) {
+        let Self {
+            meshes,
+            blases,
+            tlas,
+            records: _,
+        } = self;
+        tlas.destroy_with(ctx);
+        blases.destroy_with(ctx);
+        meshes.destroy_with(ctx);
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("buffer has no device address")]
+    NoAddress,
+    #[error("buffer / {0}")]
+    Buffer(#[from] buffer::Error),
+    #[error("acceleration structure / {0}")]
+    Acceleration(#[from] acceleration::Error),
+    #[error("command / {0}")]
+    Command(#[from] command::Error),
+}
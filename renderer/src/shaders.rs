@@ -0,0 +1,17 @@
+// SPIR-V compiled from `renderer/shaders` by the build script
+macro_rules! include_shader {
+    ($name:literal) => {
+        include_bytes!(concat!(env!("OUT_DIR"), "/", $name, ".spv"))
+    };
+}
+
+pub const PATH_TRACE_RGEN: &[u8] = include_shader!("path_trace.rgen");
+pub const PATH_TRACE_RMISS: &[u8] = include_shader!("path_trace.rmiss");
+pub const PATH_TRACE_RCHIT: &[u8] = include_shader!("path_trace.rchit");
+pub const FULLSCREEN_VERT: &[u8] = include_shader!("fullscreen.vert");
+pub const RESOLVE_FRAG: &[u8] = include_shader!("resolve.frag");
+
+// Realigned to words, which `include_bytes` does not guarantee
+pub fn words(spirv: &[u8]) -> Vec<u32> {
+    ash::util::read_spv(&mut std::io::Cursor::new(spirv)).expect("Shaders are valid SPIR-V")
+}
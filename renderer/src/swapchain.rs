@@ -1,9 +1,9 @@
 use ash::vk;
 
 use crate::{
-    base::image,
-    context::{Context, surface},
-    destroy::Destroy,
+    base::{command, image, semaphore},
+    context::{Context, device, queue},
+    destroy::{self, Destroy},
 };
 
 type Result<T> = core::result::Result<T, Error>;
@@ -13,32 +13,191 @@ mod conf {
 }
 
 pub struct Swapchain {
-    syncs: [sync_state::SyncState; conf::BUFFERING],
+    // `conf::BUFFERING` of them
+    frames: Vec<frame::Frame>,
     frame_idx: usize,
+    // reaches a frame's number once its commands are done executing
+    timeline: semaphore::Timeline,
+    submitted: u64,
+    chain: Chain,
+    // chains replaced by a recreation, along with the last frame rendering to them. Their
+    // presents are only known to be done once an image has been acquired from a newer chain.
+    retired: Vec<(u64, Chain)>,
+    // then holds them until every frame that may still reference them is done
+    deferred: destroy::Queue<Context>,
+}
+
+struct Chain {
+    format: vk::SurfaceFormatKHR,
     images: Vec<image::Image<{ image::Format::Swapchain }>>,
+    // signalled once rendering to the image with the same index is done
+    ready: Vec<semaphore::Semaphore>,
     handle: vk::SwapchainKHR,
 }
 
 impl Swapchain {
     pub fn new(ctx: &Context) -> Result<Self> {
-        let syncs = core::array::try_from_fn(|i| {
-            sync_state::SyncState::new(ctx, &format!("sync_state_{i}"))
-        })?;
+        let mut frames = Vec::with_capacity(conf::BUFFERING);
+        let mut timeline = None;
+        let result = (|| {
+            for i in 0..conf::BUFFERING {
+                frames.push(frame::Frame::new(ctx, &format!("frame_{i}"))?);
+            }
+            timeline = Some(semaphore::Timeline::new(ctx, 0, "frames")?);
+            Chain::new(ctx, vk::SwapchainKHR::null())
+        })();
+
+        match result {
+            Ok(chain) => Ok(Self {
+                frames,
+                frame_idx: 0,
+                timeline: timeline.expect("Created on success"),
+                submitted: 0,
+                chain,
+                retired: Vec::new(),
+                deferred: destroy::Queue::default(),
+            }),
+            Err(err) => {
+                timeline.destroy_with(ctx);
+                frames.destroy_with(ctx);
+                Err(err)
+            }
+        }
+    }
+
+    // Creates a new chain from the current surface config, handing the current one over as
+    // `old_swapchain`. Frames still in flight keep using the retired chain until they complete.
+    pub fn recreate(&mut self, ctx: &Context) -> Result<()> {
+        let chain = Chain::new(ctx, self.chain.handle)?;
+        self.retired
+            .push((self.submitted, std::mem::replace(&mut self.chain, chain)));
+        Ok(())
+    }
+
+    // Timeline value of the most recent frame whose commands are done executing
+    pub fn completed(&self, ctx: &Context) -> Result<u64> {
+        Ok(self.timeline.value(ctx)?)
+    }
+
+    // Timeline value of the most recently submitted frame
+    pub const fn submitted(&self) -> u64 {
+        self.submitted
+    }
+
+    pub const fn format(&self) -> vk::SurfaceFormatKHR {
+        self.chain.format
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        self.chain.images[0].extent()
+    }
+
+    // `record` draws the frame onto the acquired image, which it may leave in any state
+    pub fn render(
+        &mut self,
+        ctx: &Context,
+        record: impl FnOnce(&command::Recorder, &mut image::Image<{ image::Format::Swapchain }>),
+    ) -> Result<()> {
+        let last_submitted = self.frames[self.frame_idx].submitted;
+        self.timeline.wait(ctx, last_submitted)?;
+        self.deferred.collect(ctx, last_submitted);
+
+        // a suboptimal image is still acquired and its semaphore signalled, so the frame
+        // goes through and recreation happens afterwards
+        let (image_idx, acquired_suboptimal) =
+            self.get_next_image(ctx, *self.frames[self.frame_idx].available)?;
+        for (last_use, chain) in self.retired.drain(..) {
+            self.deferred.push(last_use, chain);
+        }
+        let image = &mut self.chain.images[image_idx as usize];
+        let ready = &self.chain.ready[image_idx as usize];
+        let frame = &mut self.frames[self.frame_idx];
+
+        frame.commands.reset(ctx)?;
+
+        let recorder = frame.commands.begin(ctx, queue::Kind::Graphics)?;
+        recorder.begin_label("frame");
+        image.assume(image::State::ACQUIRED);
+        record(&recorder, image);
+        image.transition(&recorder, image::State::PRESENT);
+        recorder.end_label();
+
+        let queue = ctx.queues().graphics();
+        let frame_number = self.submitted + 1;
+
+        command::submit(
+            ctx,
+            queue,
+            &[recorder.finish()?],
+            &[frame.available.submit_info(image::State::ACQUIRED.stage)],
+            &[
+                ready.submit_info(vk::PipelineStageFlags2::ALL_COMMANDS),
+                self.timeline
+                    .submit_info(frame_number, vk::PipelineStageFlags2::ALL_COMMANDS),
+            ],
+            vk::Fence::null(),
+        )?;
+        frame.submitted = frame_number;
+        self.submitted = frame_number;
+
+        let presented = {
+            let wait_semaphores = [**ready];
+            let swapchains = [self.chain.handle];
+            let image_indices = [image_idx];
+
+            let present_info = vk::PresentInfoKHR::default()
+                .wait_semaphores(&wait_semaphores)
+                .swapchains(&swapchains)
+                .image_indices(&image_indices);
+
+            unsafe { ctx.ext.swapchain.queue_present(**queue, &present_info) }
+        };
+
+        // the submission went through either way, so the frame is in flight
+        self.frame_idx = (self.frame_idx + 1) % conf::BUFFERING;
+
+        match presented {
+            Ok(false) if !acquired_suboptimal => Ok(()),
+            Ok(_) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => Err(Error::NeedsRecreating),
+            Err(err) => Err(Error::Present(err)),
+        }
+    }
+
+    fn get_next_image(&self, ctx: &Context, signal_to: vk::Semaphore) -> Result<(u32, bool)> {
+        unsafe {
+            ctx.ext.swapchain.acquire_next_image(
+                self.chain.handle,
+                u64::MAX,
+                signal_to,
+                vk::Fence::null(),
+            )
+        }
+        .map_err(|err| match err {
+            vk::Result::ERROR_OUT_OF_DATE_KHR => Error::NeedsRecreating,
+            err => Error::AcquireNextImage(err),
+        })
+    }
+}
+
+impl Chain {
+    fn new(ctx: &Context, old: vk::SwapchainKHR) -> Result<Self> {
+        let surface = ctx.surface.as_ref().ok_or(Error::NoSurface)?;
 
         let handle = {
             let create_info = vk::SwapchainCreateInfoKHR::default()
-                .surface(**ctx.surface)
-                .min_image_count(ctx.surface.config.image_count)
-                .image_format(surface::conf::FORMAT.format)
-                .image_color_space(surface::conf::FORMAT.color_space)
-                .image_extent(ctx.surface.config.extent)
+                .surface(***surface)
+                .min_image_count(surface.config.image_count)
+                .image_format(surface.config.format.format)
+                .image_color_space(surface.config.format.color_space)
+                .image_extent(surface.config.extent)
                 .image_array_layers(1)
                 .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
                 .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
                 .pre_transform(vk::SurfaceTransformFlagsKHR::IDENTITY)
                 .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
-                .present_mode(ctx.surface.config.present_mode)
-                .clipped(true);
+                .present_mode(surface.config.present_mode.into())
+                .clipped(true)
+                .old_swapchain(old);
 
             unsafe {
                 ctx.ext
@@ -48,125 +207,170 @@ impl Swapchain {
             }
         };
 
-        let images = unsafe {
-            ctx.ext
-                .swapchain
-                .get_swapchain_images(handle)
-                .map_err(Error::GetSwapchainImages)?
-        }
-        .into_iter()
-        .enumerate()
-        .map(|(idx, image)| {
-            image::Image::new(
-                ctx,
-                image,
-                ctx.surface.config.extent,
-                &format!("swapchain#{idx}"),
-            )
-        })
-        .collect::<image::Result<_>>()?;
-
-        Ok(Self {
-            syncs,
-            frame_idx: 0,
-            images,
+        let mut chain = Self {
+            format: surface.config.format,
+            images: Vec::new(),
+            ready: Vec::new(),
             handle,
-        })
-    }
+        };
+        let result = (|| {
+            ctx.set_debug_name(handle, "swapchain")?;
 
-    pub fn get_next_image(self, ctx: &Context, signal_to: vk::Semaphore) -> Result<u32> {
-        match unsafe {
-            ctx.ext
-                .swapchain
-                .acquire_next_image(self.handle, u64::MAX, signal_to, vk::Fence::null())
-                .map_err(Error::AcquireNextImage)?
-        } {
-            (_, true) => Err(Error::NeedsRecreating),
-            (image_index, false) => Ok(image_index),
+            let images = unsafe {
+                ctx.ext
+                    .swapchain
+                    .get_swapchain_images(handle)
+                    .map_err(Error::GetSwapchainImages)?
+            };
+            for (idx, image) in images.into_iter().enumerate() {
+                chain.images.push(image::Image::new(
+                    ctx,
+                    image,
+                    surface.config.format.format,
+                    surface.config.extent,
+                    &format!("swapchain#{idx}"),
+                )?);
+                chain.ready.push(semaphore::Semaphore::new(
+                    ctx,
+                    &format!("swapchain#{idx}:ready"),
+                )?);
+            }
+            Ok(())
+        })();
+
+        match result {
+            Ok(()) => Ok(chain),
+            Err(err) => {
+                chain.destroy_with(ctx);
+                Err(err)
+            }
         }
     }
 }
 
-impl Destroy<Context> for Swapchain {
+impl Destroy<Context> for Chain {
     fn destroy_with(&mut self, ctx: &Context) {
         let Self {
-            syncs,
-            frame_idx: _,
+            format: _,
             images,
+            ready,
             handle,
         } = self;
 
-        syncs.destroy_with(ctx);
         images.destroy_with(ctx);
+        ready.destroy_with(ctx);
+        ctx.untrack(*handle);
         unsafe {
             ctx.ext.swapchain.destroy_swapchain(*handle, None);
         }
+        *handle = vk::SwapchainKHR::null();
+    }
+}
+
+impl Drop for Chain {
+    fn drop(&mut self) {
+        destroy::debug_assert_destroyed(self.handle, "swapchain");
+    }
+}
+
+impl Destroy<Context> for Swapchain {
+    fn destroy_with(&mut self, ctx: &Context) {
+        let Self {
+            frames,
+            frame_idx: _,
+            timeline,
+            submitted: _,
+            chain,
+            retired,
+            deferred,
+        } = self;
+
+        frames.destroy_with(ctx);
+        timeline.destroy_with(ctx);
+        for (_, chain) in retired {
+            chain.destroy_with(ctx);
+        }
+        deferred.destroy_with(ctx);
+        chain.destroy_with(ctx);
     }
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
+    #[error("no surface to present to")]
+    NoSurface,
     #[error("failed to create swapchain / {0}")]
     Create(vk::Result),
     #[error("failed to get swapchain images / {0}")]
     GetSwapchainImages(vk::Result),
     #[error("failed to acquire next image / {0}")]
     AcquireNextImage(vk::Result),
+    #[error("failed to present frame / {0}")]
+    Present(vk::Result),
     #[error("needs recreating")]
     NeedsRecreating,
     #[error("image / {0}")]
     Image(#[from] image::Error),
-    #[error("sync states / {0}")]
-    SyncStates(#[from] sync_state::Error),
+    #[error("semaphore / {0}")]
+    Semaphore(#[from] semaphore::Error),
+    #[error("command / {0}")]
+    Command(#[from] command::Error),
+    #[error("frame / {0}")]
+    Frame(#[from] frame::Error),
+    #[error("device / {0}")]
+    Device(#[from] device::Error),
 }
 
-mod sync_state {
+mod frame {
     use crate::{
-        base::{fence, semaphore},
+        base::{command, semaphore},
         context::Context,
         destroy::Destroy,
     };
 
     type Result<T> = core::result::Result<T, Error>;
 
-    pub struct SyncState {
-        available: semaphore::Semaphore,
-        ready: semaphore::Semaphore,
-        presented: fence::Fence,
+    pub struct Frame {
+        pub commands: command::Pools,
+        pub available: semaphore::Semaphore,
+        // value of the swapchain timeline signalled by this frame's last submission
+        pub submitted: u64,
     }
 
-    impl SyncState {
+    impl Frame {
         pub fn new(ctx: &Context, name_prefix: &str) -> Result<Self> {
-            let available = semaphore::Semaphore::new(ctx, &format!("{name_prefix}:available"))?;
-            let ready = semaphore::Semaphore::new(ctx, &format!("{name_prefix}:ready"))?;
-            let presented = fence::Fence::new(ctx, true, &format!("{name_prefix}:ready"))?;
-
-            Ok(Self {
-                available,
-                ready,
-                presented,
-            })
+            let mut commands = command::Pools::new(ctx, &format!("{name_prefix}:commands"))?;
+            match semaphore::Semaphore::new(ctx, &format!("{name_prefix}:available")) {
+                Ok(available) => Ok(Self {
+                    commands,
+                    available,
+                    submitted: 0,
+                }),
+                Err(err) => {
+                    commands.destroy_with(ctx);
+                    Err(err.into())
+                }
+            }
         }
     }
 
-    impl Destroy<Context> for SyncState {
+    impl Destroy<Context> for Frame {
         fn destroy_with(&mut self, ctx: &Context) {
             let Self {
+                commands,
                 available,
-                ready,
-                presented,
+                submitted: _,
             } = self;
 
+            commands.destroy_with(ctx);
             available.destroy_with(ctx);
-            ready.destroy_with(ctx);
-            presented.destroy_with(ctx);
         }
     }
 
     #[derive(Debug, thiserror::Error)]
     pub enum Error {
-        #[error("fence / {0}")]
-        Fence(#[from] fence::Error),
+        #[error("command / {0}")]
+        Command(#[from] command::Error),
         #[error("semaphore / {0}")]
         Semaphore(#[from] semaphore::Error),
     }